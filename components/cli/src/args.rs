@@ -0,0 +1,281 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use engine::{ClientId, Column, InvalidRowsLimit, OutputOrder, OutputSchema, ProcessOptions};
+
+use crate::exit_code;
+
+const DEFAULT_SERVE_ADDR: &str = "127.0.0.1:8080";
+const DEFAULT_TCP_ADDR: &str = "127.0.0.1:9000";
+const DEFAULT_FOLLOW_INTERVAL_SECS: u64 = 60;
+/// options of the balances output and of the run summary, which exports don't write
+const BALANCES_ONLY: [&str; 6] =
+    ["sort", "schema_version", "columns", "audit_log", "stats", "stats_json"];
+
+/// Process payment transactions and print balances of client accounts
+#[derive(Debug, Parser)]
+#[command(name = "transaction", version, after_help = exit_code::HELP)]
+pub(crate) struct Cli {
+    #[command(subcommand)]
+    pub(crate) command: Command,
+
+    /// log filter, eg. `debug` or `engine=trace`
+    #[arg(long, global = true, env = "RUST_LOG", default_value = "error")]
+    pub(crate) log_level: String,
+
+    /// format of logs written to stderr
+    #[arg(long, global = true, env = "TRANSACTION_LOG_FORMAT", default_value = "text")]
+    pub(crate) log_format: LogFormat,
+
+    /// TOML file with engine policies, see `print-default-config`
+    #[arg(long, global = true, env = "TRANSACTION_CONFIG", value_name = "FILE")]
+    pub(crate) config: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub(crate) enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum Command {
+    /// Process transactions and print final balances (default when no subcommand is given)
+    Process(ProcessArgs),
+    /// Check the whole input without processing it and print every problem with its line; exits
+    /// with 1 when there are any
+    Validate {
+        /// csv with `type,client,tx,amount` header
+        input: PathBuf,
+    },
+    /// Process transactions on top of engine state saved by `watch --state` or earlier replays
+    Replay(ReplayArgs),
+    /// Show what transactions would do to engine state saved by `watch --state` or replays,
+    /// without applying them
+    WhatIf {
+        /// csv with `type,client,tx,amount` header
+        input: PathBuf,
+        /// engine state to start from. It's only read
+        #[arg(long)]
+        state: PathBuf,
+        /// print the result as json
+        #[arg(long)]
+        json: bool,
+    },
+    /// Process transactions and print only summary of the run
+    Stats {
+        /// csv with `type,client,tx,amount` header
+        input: PathBuf,
+        /// print the summary as json
+        #[arg(long)]
+        json: bool,
+    },
+    /// Compare two balance outputs by client and column; exits with 1 when they differ
+    Diff {
+        /// balances written by `process`
+        left: PathBuf,
+        /// balances to compare with
+        right: PathBuf,
+        /// largest difference of amounts which are still considered equal
+        #[arg(long, default_value_t = 0.0, value_parser = parse_tolerance)]
+        tolerance: f64,
+    },
+    /// Explore engine state interactively: step through records, type transactions and inspect
+    /// wallets
+    Repl {
+        /// csv file with records to queue for stepping through
+        input: Option<PathBuf>,
+        /// engine state to start from
+        #[arg(long)]
+        state: Option<PathBuf>,
+    },
+    /// Print the default engine config, a starting point of a `--config` file
+    PrintDefaultConfig,
+    /// Serve the engine over HTTP
+    Serve {
+        /// address to listen on
+        #[arg(long, default_value = DEFAULT_SERVE_ADDR)]
+        addr: SocketAddr,
+        #[command(flatten)]
+        daemon: DaemonArgs,
+    },
+    /// Accept csv rows streamed over TCP and ack each of them
+    Tcp {
+        /// address to listen on
+        #[arg(long, default_value = DEFAULT_TCP_ADDR)]
+        addr: SocketAddr,
+        #[command(flatten)]
+        daemon: DaemonArgs,
+    },
+    /// Process csv files dropped to a directory
+    Watch {
+        /// directory to watch. Files are moved to its `processed/` or `failed/` subdirectory
+        inbox: PathBuf,
+        /// load engine state from the file on start and save it after each processed file
+        #[arg(long)]
+        state: Option<PathBuf>,
+        #[command(flatten)]
+        daemon: DaemonArgs,
+    },
+}
+
+/// Options of the balances output
+#[derive(Debug, Args)]
+pub(crate) struct OutputArgs {
+    /// order of printed clients: unsorted, client or balance
+    #[arg(long, default_value_t)]
+    pub(crate) sort: OutputOrder,
+    /// output schema version
+    #[arg(long, default_value_t = OutputSchema::LATEST_VERSION)]
+    pub(crate) schema_version: u32,
+    /// extra output columns: last_tx, open_disputes, currency
+    #[arg(long, value_delimiter = ',')]
+    pub(crate) columns: Vec<Column>,
+    /// code printed in the currency column and used in camt.053 documents
+    #[arg(long)]
+    pub(crate) currency: Option<String>,
+    /// append every change of a wallet to the file, one json entry per line
+    #[arg(long, value_name = "FILE")]
+    pub(crate) audit_log: Option<PathBuf>,
+}
+
+impl OutputArgs {
+    pub(crate) fn options(&self) -> Result<ProcessOptions, engine::EngineError> {
+        let mut schema = OutputSchema::new(self.schema_version)?;
+        for column in self.columns.iter() {
+            schema = schema.with_column(*column);
+        }
+        if let Some(currency) = &self.currency {
+            schema = schema.with_currency(currency);
+        }
+        Ok(ProcessOptions {
+            order: self.sort,
+            schema,
+            audit_log: self.audit_log.clone(),
+            ..Default::default()
+        })
+    }
+}
+
+/// Options of modes which keep running till they are interrupted
+#[derive(Debug, Args)]
+pub(crate) struct DaemonArgs {
+    /// serve Prometheus metrics at `http://<addr>/metrics`
+    #[arg(long, value_name = "ADDR")]
+    pub(crate) metrics_addr: Option<SocketAddr>,
+    /// append every change of a wallet to the file, one json entry per line
+    #[arg(long, value_name = "FILE")]
+    pub(crate) audit_log: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct ProcessArgs {
+    /// csv with `type,client,tx,amount` header, or MT940 statement with `--mt940`
+    pub(crate) input: PathBuf,
+    #[command(flatten)]
+    pub(crate) output: OutputArgs,
+    /// print statements of the given clients instead of balances
+    #[arg(
+        long,
+        value_name = "ID,...",
+        value_delimiter = ',',
+        group = "export",
+        conflicts_with_all = BALANCES_ONLY
+    )]
+    pub(crate) statement: Option<Vec<ClientId>>,
+    /// print audit trails of the given clients instead of balances
+    #[arg(
+        long,
+        value_name = "ID,...",
+        value_delimiter = ',',
+        group = "export",
+        conflicts_with_all = BALANCES_ONLY
+    )]
+    pub(crate) audit: Option<Vec<ClientId>>,
+    /// print camt.053 document of the given clients instead of balances
+    #[arg(
+        long,
+        value_name = "ID,...",
+        value_delimiter = ',',
+        group = "export",
+        conflicts_with_all = BALANCES_ONLY
+    )]
+    pub(crate) camt053: Option<Vec<ClientId>>,
+    /// message id of the camt.053 document, generated from current time by default
+    #[arg(long, requires = "camt053")]
+    pub(crate) message_id: Option<String>,
+    /// read MT940 statement; the file maps statement accounts to clients (`account,client`)
+    #[arg(long, value_name = "ACCOUNTS", conflicts_with_all = ["export", "follow"])]
+    pub(crate) mt940: Option<PathBuf>,
+    /// keep reading rows appended to the input and print balances periodically
+    #[arg(long, conflicts_with = "export")]
+    pub(crate) follow: bool,
+    /// seconds between balances printed in follow mode
+    #[arg(
+        long,
+        default_value_t = DEFAULT_FOLLOW_INTERVAL_SECS,
+        value_parser = clap::value_parser!(u64).range(1..),
+        requires = "follow"
+    )]
+    pub(crate) interval: u64,
+    /// serve Prometheus metrics at `http://<addr>/metrics` in follow mode
+    #[arg(long, value_name = "ADDR", requires = "follow")]
+    pub(crate) metrics_addr: Option<SocketAddr>,
+    /// skip rows which aren't correct transactions instead of stopping on the first one
+    #[arg(long, conflicts_with_all = ["export", "mt940"])]
+    pub(crate) skip_invalid: bool,
+    /// stop when more rows than this are skipped
+    #[arg(long, value_name = "COUNT", requires = "skip_invalid")]
+    pub(crate) max_invalid: Option<u64>,
+    /// stop when share of skipped rows is higher than this, eg. 0.01
+    #[arg(long, value_name = "RATIO", value_parser = parse_ratio, requires = "skip_invalid")]
+    pub(crate) max_invalid_ratio: Option<f64>,
+    /// show progress, throughput, rejections and top clients in the terminal while processing
+    #[arg(long, conflicts_with_all = ["export", "mt940", "follow"])]
+    pub(crate) dashboard: bool,
+    /// print summary of the run to stderr
+    #[arg(long)]
+    pub(crate) stats: bool,
+    /// write summary of the run as json
+    #[arg(long, value_name = "FILE")]
+    pub(crate) stats_json: Option<PathBuf>,
+}
+
+impl ProcessArgs {
+    pub(crate) fn options(&self) -> Result<ProcessOptions, engine::EngineError> {
+        let mut options = self.output.options()?;
+        options.skip_invalid = self.skip_invalid.then_some(InvalidRowsLimit {
+            max_count: self.max_invalid,
+            max_ratio: self.max_invalid_ratio,
+        });
+        Ok(options)
+    }
+}
+
+fn parse_ratio(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(ratio) if (0.0..=1.0).contains(&ratio) => Ok(ratio),
+        _ => Err("ratio must be a number between 0 and 1".to_string()),
+    }
+}
+
+fn parse_tolerance(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(tolerance) if tolerance.is_finite() && tolerance >= 0.0 => Ok(tolerance),
+        _ => Err("tolerance must be a non-negative number".to_string()),
+    }
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct ReplayArgs {
+    /// csv with `type,client,tx,amount` header
+    pub(crate) input: PathBuf,
+    /// engine state to start from
+    #[arg(long)]
+    pub(crate) state: PathBuf,
+    /// write the resulting state back to `--state`
+    #[arg(long)]
+    pub(crate) save: bool,
+    #[command(flatten)]
+    pub(crate) output: OutputArgs,
+}
@@ -0,0 +1,156 @@
+use std::{
+    io::Read,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use console::{Style, Term};
+use engine::{ClientVolume, Metrics, RunStats};
+
+/// how often the dashboard is redrawn
+const REFRESH_INTERVAL: Duration = Duration::from_millis(200);
+const TOP_CLIENTS: usize = 5;
+const PROGRESS_BAR_WIDTH: usize = 40;
+
+/// Reader which counts bytes read so far, so the dashboard knows how far in the input we are
+pub(crate) struct ProgressReader<R> {
+    inner: R,
+    read: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.read.fetch_add(read as u64, Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+/// Live view of a run drawn to stderr, so it doesn't mix with balances written to stdout. It's
+/// redrawn till [`Dashboard::finish`] is called, which draws the final state once more. When
+/// stderr isn't a terminal, only the final state is drawn
+pub(crate) struct Dashboard {
+    stop: mpsc::Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+impl Dashboard {
+    /// start drawing metrics of the run reading `input` of `size` bytes. Returns the reader the
+    /// run must read from
+    pub(crate) fn start<R: Read>(
+        input: R,
+        size: u64,
+        metrics: Arc<Metrics>,
+    ) -> (Self, ProgressReader<R>) {
+        let read = Arc::new(AtomicU64::new(0));
+        let reader = ProgressReader { inner: input, read: read.clone() };
+
+        let (stop, stopped) = mpsc::channel();
+        let thread = std::thread::spawn(move || {
+            let term = Term::stderr();
+            let mut drawn = 0;
+            loop {
+                let finished = !matches!(
+                    stopped.recv_timeout(REFRESH_INTERVAL),
+                    Err(mpsc::RecvTimeoutError::Timeout)
+                );
+                // without a terminal frames can't be redrawn in place, so only the last one is
+                // written
+                if !term.is_term() && !finished {
+                    continue;
+                }
+
+                let frame = render(
+                    &metrics.stats(),
+                    &metrics.top_clients(TOP_CLIENTS),
+                    read.load(Ordering::Relaxed),
+                    size,
+                );
+                // the dashboard is only an aid, a failed redraw must not stop the run
+                let _ = term.clear_last_lines(drawn);
+                let _ = term.write_line(&frame.join("\n"));
+                drawn = frame.len();
+                if finished {
+                    break;
+                }
+            }
+        });
+        (Self { stop, thread }, reader)
+    }
+
+    pub(crate) fn finish(self) {
+        let _ = self.stop.send(());
+        let _ = self.thread.join();
+    }
+}
+
+/// lines of a single frame of the dashboard
+fn render(stats: &RunStats, top: &[ClientVolume], read: u64, size: u64) -> Vec<String> {
+    let label = Style::new().bold();
+    let ratio = if size > 0 { (read as f64 / size as f64).min(1.0) } else { 1.0 };
+    let filled = (ratio * PROGRESS_BAR_WIDTH as f64) as usize;
+
+    let mut lines = vec![
+        format!(
+            "{} [{}{}] {:>3.0}% ({read} of {size} bytes)",
+            label.apply_to("progress"),
+            "#".repeat(filled),
+            "-".repeat(PROGRESS_BAR_WIDTH - filled),
+            ratio * 100.0
+        ),
+        format!(
+            "{} {} (invalid: {}), {:.0} rows/s, elapsed {:.1}s",
+            label.apply_to("rows"),
+            stats.rows,
+            stats.invalid_rows,
+            stats.rows_per_sec,
+            stats.elapsed_secs
+        ),
+        format!(
+            "{} {}, clients: {}, open disputes: {}, {}",
+            label.apply_to("accepted"),
+            stats.accepted,
+            stats.clients,
+            stats.open_disputes,
+            Style::new().red().apply_to(format!("locked accounts: {}", stats.locked_accounts))
+        ),
+        format!("{} {}", label.apply_to("rejected"), stats.rejected_total()),
+    ];
+    for (reason, count) in stats.rejected.iter().filter(|(_, count)| **count > 0) {
+        lines.push(format!("  {reason}: {count}"));
+    }
+
+    lines.push(format!("{}", label.apply_to("top clients by volume")));
+    for client in top {
+        lines.push(format!(
+            "  {:>10}  {:>8} tx  {}",
+            client.client, client.transactions, client.volume
+        ));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_progress_and_top_clients() {
+        let metrics = Metrics::default();
+        let top = [ClientVolume { client: 7, transactions: 3, volume: 12.into() }];
+
+        console::set_colors_enabled(false);
+        let frame = render(&metrics.stats(), &top, 25, 100);
+        assert_eq!(
+            frame[0],
+            format!("progress [{}{}]  25% (25 of 100 bytes)", "#".repeat(10), "-".repeat(30))
+        );
+        assert_eq!(frame[2], "accepted 0, clients: 0, open disputes: 0, locked accounts: 0");
+        assert_eq!(frame[3], "rejected 0");
+        assert_eq!(frame.last().unwrap(), "           7         3 tx  12");
+    }
+}
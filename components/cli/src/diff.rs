@@ -0,0 +1,128 @@
+use std::{collections::BTreeMap, io::Write, path::Path};
+
+use anyhow::Context;
+use engine::ClientId;
+
+/// Compare two balance outputs, matching rows by client id (the first column). Columns are matched
+/// by name, and numbers which differ by at most `tolerance` are equal. Every difference is written
+/// to `out`. Returns whether the outputs differ
+pub(crate) fn diff<W: Write>(
+    left: &Path,
+    right: &Path,
+    tolerance: f64,
+    mut out: W,
+) -> anyhow::Result<bool> {
+    let left = Balances::read(left)?;
+    let right = Balances::read(right)?;
+
+    let mut differ = false;
+    if left.columns != right.columns {
+        writeln!(out, "~ header: {} -> {}", left.columns.join(","), right.columns.join(","))?;
+        differ = true;
+    }
+
+    for (client, left_row) in left.rows.iter() {
+        let Some(right_row) = right.rows.get(client) else {
+            writeln!(out, "- {client}: {}", left_row.join(","))?;
+            differ = true;
+            continue;
+        };
+
+        let changes = left
+            .columns
+            .iter()
+            .enumerate()
+            .skip(1)
+            .filter_map(|(idx, column)| {
+                let right_idx = right.columns.iter().position(|c| c == column)?;
+                let (l, r) = (left_row.get(idx)?, right_row.get(right_idx)?);
+                (!same_value(l, r, tolerance)).then(|| format!("{column} {l} -> {r}"))
+            })
+            .collect::<Vec<_>>();
+        if !changes.is_empty() {
+            writeln!(out, "~ {client}: {}", changes.join(", "))?;
+            differ = true;
+        }
+    }
+    for (client, right_row) in
+        right.rows.iter().filter(|(client, _)| !left.rows.contains_key(client))
+    {
+        writeln!(out, "+ {client}: {}", right_row.join(","))?;
+        differ = true;
+    }
+    Ok(differ)
+}
+
+/// numbers are compared with the tolerance, anything else must be the same
+fn same_value(left: &str, right: &str, tolerance: f64) -> bool {
+    match (left.parse::<f64>(), right.parse::<f64>()) {
+        (Ok(l), Ok(r)) => (l - r).abs() <= tolerance,
+        _ => left == right,
+    }
+}
+
+/// Balances written by `print_wallets`: columns of the header and fields of rows by client id
+struct Balances {
+    columns: Vec<String>,
+    rows: BTreeMap<ClientId, Vec<String>>,
+}
+
+impl Balances {
+    fn read(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("can't read \"{}\"", path.display()))?;
+        let mut rdr = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(file);
+        let columns = rdr.headers()?.iter().map(str::to_string).collect();
+
+        let mut rows = BTreeMap::new();
+        for row in rdr.into_records() {
+            let row = row.with_context(|| format!("can't read \"{}\"", path.display()))?;
+            let line = row.position().map_or(0, |pos| pos.line());
+            let client = row[0].parse::<ClientId>().with_context(|| {
+                format!("{} line {line}: incorrect client id \"{}\"", path.display(), &row[0])
+            })?;
+            let fields = row.iter().map(str::to_string).collect();
+            if rows.insert(client, fields).is_some() {
+                anyhow::bail!("{} line {line}: client {client} is listed again", path.display());
+            }
+        }
+        Ok(Self { columns, rows })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_changed_added_and_removed_clients() {
+        let dir = std::env::temp_dir().join(format!("diff_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (left, right) = (dir.join("left.csv"), dir.join("right.csv"));
+        let header = "client,available,held,total,locked\n";
+        let left_rows = "2,1,0,1,false\n10,2,0,2,false\n4,1.5,0,1.5,false\n";
+        let right_rows = "10,2.0,0,2,false\n3,1,0,1,false\n4,1.5001,0,1.5001,true\n";
+        std::fs::write(&left, format!("{header}{left_rows}")).unwrap();
+        std::fs::write(&right, format!("{header}{right_rows}")).unwrap();
+
+        let mut out = Vec::new();
+        assert!(diff(&left, &right, 0.001, &mut out).unwrap());
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "- 2: 2,1,0,1,false\n~ 4: locked false -> true\n+ 3: 3,1,0,1,false\n"
+        );
+
+        let mut out = Vec::new();
+        assert!(diff(&left, &right, 0.0, &mut out).unwrap());
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .contains("~ 4: available 1.5 -> 1.5001, total 1.5 -> 1.5001, locked false -> true\n"));
+        assert!(!diff(&left, &left, 0.0, std::io::sink()).unwrap());
+
+        std::fs::write(&right, format!("{header}{right_rows}10,2,0,2,false\n")).unwrap();
+        let error = diff(&left, &right, 0.0, std::io::sink()).unwrap_err().to_string();
+        assert_eq!(error, format!("{} line 5: client 10 is listed again", right.display()));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,54 @@
+use engine::EngineError;
+
+pub(crate) const SUCCESS: u8 = 0;
+/// check failed: `validate` found problems or `diff` found differences
+pub(crate) const CHECK_FAILED: u8 = 1;
+/// incorrect command line, reported by clap
+pub(crate) const USAGE: u8 = 2;
+/// input can't be processed: malformed csv, incorrect record or unknown client
+pub(crate) const INVALID_INPUT: u8 = 3;
+/// file or network can't be read or written
+pub(crate) const IO: u8 = 4;
+/// incorrect engine state, config, output schema or audit log
+pub(crate) const INVALID_STATE: u8 = 5;
+/// anything else, eg. a client task failed
+pub(crate) const INTERNAL: u8 = 6;
+
+pub(crate) const HELP: &str = "\
+Exit codes:
+  0  success
+  1  check failed: `validate` found problems or `diff` found differences
+  2  incorrect command line
+  3  invalid input: malformed csv, incorrect record, MT940 error or unknown client
+  4  I/O error
+  5  invalid engine state, config, output schema or audit log
+  6  internal error";
+
+/// exit code of the error which ended the program
+pub(crate) fn of(error: &anyhow::Error) -> u8 {
+    if let Some(error) = error.downcast_ref::<EngineError>() {
+        return of_engine_error(error);
+    }
+    if error.downcast_ref::<std::io::Error>().is_some() {
+        return IO;
+    }
+    INTERNAL
+}
+
+fn of_engine_error(error: &EngineError) -> u8 {
+    match error {
+        EngineError::ClientNotExists(_)
+        | EngineError::CsvError(_)
+        | EngineError::InputFileError(_)
+        | EngineError::Mt940Error(_)
+        | EngineError::RecordError(_) => INVALID_INPUT,
+        EngineError::IoError(_) => IO,
+        EngineError::AuditError(_)
+        | EngineError::ConfigError(_)
+        | EngineError::OutputSchemaError(_)
+        | EngineError::StateError(_) => INVALID_STATE,
+        EngineError::FailedToGetWallet
+        | EngineError::JoinError(_)
+        | EngineError::SendTransactionError(_) => INTERNAL,
+    }
+}
@@ -0,0 +1,213 @@
+use std::{future::Future, io::Write, path::Path, time::Duration};
+
+use engine::{Engine, EngineError, ProcessOptions, Record};
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt, AsyncSeekExt, BufReader},
+};
+
+/// how often we check if new rows were appended to the input
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Keep reading rows appended to the input (like `tail -f`) and process them as they come.
+///
+/// Current balances are written every `interval`, on SIGUSR1 and once more when `shutdown`
+/// completes. Each write is a complete csv (header and rows) followed by an empty line.
+///
+/// Invalid rows are logged, counted and skipped. Following stops only when they go over
+/// [`ProcessOptions::skip_invalid`] limits, if any.
+pub(crate) async fn follow<W: Write>(
+    path: &Path,
+    engine: &mut Engine,
+    options: &ProcessOptions,
+    interval: Duration,
+    mut out: W,
+    shutdown: impl Future<Output = ()>,
+) -> anyhow::Result<()> {
+    let mut reader = BufReader::new(File::open(path).await?);
+    let mut position = 0;
+    let mut line = String::new();
+    let mut line_no = 0;
+    let (mut rows, mut invalid) = (0, 0);
+
+    let mut ticker = tokio::time::interval(interval);
+    // first tick completes immediately, there is nothing to print yet
+    ticker.tick().await;
+
+    let mut user_signal = UserSignal::new()?;
+    tokio::pin!(shutdown);
+
+    loop {
+        // process every complete line which is already in the file. Incomplete last line stays in
+        // the buffer till the rest of it is appended
+        loop {
+            let read = reader.read_line(&mut line).await?;
+            position += read as u64;
+            if read == 0 || !line.ends_with('\n') {
+                break;
+            }
+
+            line_no += 1;
+            let row = line.trim();
+            if !(row.is_empty() || row.starts_with('#') || Record::is_csv_header(row)) {
+                rows += 1;
+                match process_row(engine, row, line_no).await {
+                    Err(e) if e.is_invalid_row() => {
+                        tracing::warn!(line = line_no, error = %e, "Skipping invalid row");
+                        invalid += 1;
+                        if let Some(limit) = options.skip_invalid {
+                            limit.check(invalid, rows, false)?;
+                        }
+                    },
+                    result => result?,
+                }
+            }
+            line.clear();
+        }
+
+        if tokio::fs::metadata(path).await?.len() < position {
+            tracing::warn!(path = %path.display(), "File was truncated, reading it again");
+            reader.seek(std::io::SeekFrom::Start(0)).await?;
+            position = 0;
+            line_no = 0;
+            line.clear();
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(POLL_INTERVAL) => {},
+            _ = ticker.tick() => write_balances(engine, options, &mut out).await?,
+            _ = user_signal.recv() => write_balances(engine, options, &mut out).await?,
+            _ = &mut shutdown => {
+                write_balances(engine, options, &mut out).await?;
+                return Ok(());
+            },
+        }
+    }
+}
+
+async fn process_row(engine: &mut Engine, row: &str, line_no: u64) -> Result<(), EngineError> {
+    let record = Record::from_csv_line(row).inspect_err(|_| engine.invalid_row())?;
+    engine.process_record(record.at_line(line_no)).await
+}
+
+async fn write_balances<W: Write>(
+    engine: &mut Engine,
+    options: &ProcessOptions,
+    out: &mut W,
+) -> anyhow::Result<()> {
+    engine.write_balances(&mut *out, options.order, &options.schema).await?;
+    writeln!(out)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// SIGUSR1 on unix, never completes on other platforms
+struct UserSignal {
+    #[cfg(unix)]
+    signal: tokio::signal::unix::Signal,
+}
+
+impl UserSignal {
+    fn new() -> std::io::Result<Self> {
+        Ok(Self {
+            #[cfg(unix)]
+            signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined1())?,
+        })
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        self.signal.recv().await;
+        #[cfg(not(unix))]
+        std::future::pending::<()>().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, sync::Arc};
+
+    use engine::{InvalidRowsLimit, Metrics, OutputOrder};
+
+    use super::*;
+
+    /// wait till the engine has processed `count` rows, valid or not
+    async fn wait_for_rows(metrics: &Arc<Metrics>, count: u64) {
+        let processed = || {
+            let stats = metrics.stats();
+            stats.invalid_rows + stats.accepted + stats.rejected_total()
+        };
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while processed() < count {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("rows weren't processed in time");
+    }
+
+    #[tokio::test]
+    async fn process_appended_rows() {
+        let path = std::env::temp_dir().join(format!("follow_{}.csv", std::process::id()));
+        std::fs::write(&path, "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 2, 2,")
+            .unwrap();
+
+        let mut engine = Engine::new();
+        let metrics = engine.metrics();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let follower = tokio::spawn({
+            let path = path.clone();
+            async move {
+                let options = ProcessOptions { order: OutputOrder::ClientId, ..Default::default() };
+                let mut out = Cursor::new(Vec::new());
+                follow(&path, &mut engine, &options, Duration::from_secs(60), &mut out, async {
+                    let _ = stopped.await;
+                })
+                .await
+                .unwrap();
+                String::from_utf8(out.into_inner()).unwrap()
+            }
+        });
+
+        wait_for_rows(&metrics, 1).await;
+        // finish the incomplete row and append a new one. The invalid row is skipped
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b" 2.0\ndeposit, 1, x, 1.0\nwithdrawal, 1, 3, 0.5\n").unwrap();
+        wait_for_rows(&metrics, 4).await;
+
+        stop.send(()).unwrap();
+        let output = follower.await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            output,
+            "client,available,held,total,locked\n1,0.5,0,0.5,false\n2,2,0,2,false\n\n"
+        );
+        assert_eq!(metrics.stats().invalid_rows, 1);
+    }
+
+    #[tokio::test]
+    async fn stop_over_invalid_rows_limit() {
+        let path = std::env::temp_dir().join(format!("follow_limit_{}.csv", std::process::id()));
+        std::fs::write(&path, "type, client, tx, amount\ndeposit, 1, x,\ndeposit, 1, 1,\n")
+            .unwrap();
+
+        let mut engine = Engine::new();
+        let limit = InvalidRowsLimit { max_count: Some(1), ..Default::default() };
+        let options = ProcessOptions { skip_invalid: Some(limit), ..Default::default() };
+        let shutdown = std::future::pending();
+        let result = follow(
+            &path,
+            &mut engine,
+            &options,
+            Duration::from_secs(60),
+            std::io::sink(),
+            shutdown,
+        )
+        .await;
+        std::fs::remove_file(&path).unwrap();
+
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("too many invalid rows: 2 of 2 read (max 1)"), "{error}");
+    }
+}
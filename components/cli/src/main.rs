@@ -1,38 +1,333 @@
-use std::ffi::OsString;
+use std::{
+    ffi::OsString, fs::File, io::IsTerminal, net::SocketAddr, path::Path, process::ExitCode,
+    sync::Arc,
+};
 
+use anyhow::Context;
+use clap::Parser;
 use console::Style;
+use engine::{
+    Camt053Options, Engine, EngineConfig, Metrics, OutputSchema, ProcessOptions, Record, RunStats,
+};
+use tokio::sync::Mutex;
+use tracing_subscriber::EnvFilter;
 
-fn main() -> anyhow::Result<()> {
-    let level_filter = log::LevelFilter::Error;
-    env_logger::Builder::new().filter_level(level_filter).init();
+use crate::{
+    args::{Cli, Command, DaemonArgs, LogFormat, ProcessArgs, ReplayArgs},
+    dashboard::Dashboard,
+};
 
-    let args = std::env::args_os().collect::<Vec<OsString>>();
+mod args;
+mod dashboard;
+mod diff;
+mod exit_code;
+mod follow;
+mod metrics;
+mod repl;
+mod serve;
+mod tcp;
+mod watch;
+mod what_if;
 
-    if args.len() != 2 {
-        print_help();
-        return Ok(());
+/// Names of subcommands and flags handled before them. When the first argument is none of them, it's
+/// the input of the default `process` subcommand, like before the subcommands were added
+const SUBCOMMANDS: [&str; 12] = [
+    "process",
+    "validate",
+    "replay",
+    "what-if",
+    "stats",
+    "diff",
+    "repl",
+    "serve",
+    "tcp",
+    "watch",
+    "print-default-config",
+    "help",
+];
+const TOP_LEVEL_FLAGS: [&str; 4] = ["-h", "--help", "-V", "--version"];
+const GLOBAL_FLAGS: [&str; 3] = ["--log-level", "--log-format", "--config"];
+
+/// latest wallet changes kept per client for `disputes` of the repl and the audit trail endpoint
+/// of the server. Older ones are only in the audit log
+pub(crate) const AUDIT_TRAIL_LIMIT: usize = 1000;
+
+/// Engine shared by all connections of long-lived modes. Records are sent to the client tasks under
+/// the lock, so transactions of the same client are processed in the order they came in
+pub(crate) type SharedEngine = Arc<Mutex<Engine>>;
+
+fn main() -> ExitCode {
+    let cli = Cli::parse_from(with_default_subcommand(std::env::args_os().collect()));
+    init_tracing(&cli.log_level, cli.log_format);
+
+    match load_config(cli.config.as_deref()).and_then(|config| run(cli.command, config)) {
+        Ok(code) => ExitCode::from(code),
+        Err(e) => {
+            eprintln!("{}: {e:#}", Style::new().red().bold().apply_to("ERR"));
+            ExitCode::from(exit_code::of(&e))
+        },
     }
+}
 
-    let Some(input_csv) = args[1].to_str() else {
-        report_error("Incorrect CLI arg", 2);
+/// insert `process` subcommand when it's missing, eg. `transaction --sort client input.csv`
+fn with_default_subcommand(mut args: Vec<OsString>) -> Vec<OsString> {
+    let mut idx = 1;
+    while let Some(arg) = args.get(idx) {
+        if GLOBAL_FLAGS.iter().any(|flag| arg == *flag) {
+            idx += 2;
+        } else if GLOBAL_FLAGS.iter().any(|flag| arg.to_string_lossy().starts_with(flag)) {
+            idx += 1;
+        } else {
+            break;
+        }
+    }
+
+    let Some(arg) = args.get(idx) else {
+        return args;
     };
+    if !SUBCOMMANDS.iter().chain(TOP_LEVEL_FLAGS.iter()).any(|name| arg == *name) {
+        args.insert(1, "process".into());
+    }
+    args
+}
+
+/// logs go to stderr, so they never mix with the csv written to stdout
+fn init_tracing(level: &str, format: LogFormat) {
+    let filter = EnvFilter::try_new(level).unwrap_or_else(|e| {
+        eprintln!("{}: incorrect log level: {e}", Style::new().red().bold().apply_to("ERR"));
+        std::process::exit(exit_code::USAGE.into());
+    });
+
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter).with_writer(std::io::stderr);
+    match format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
+}
+
+/// engine policies from the config file, the default ones without it
+fn load_config(path: Option<&Path>) -> anyhow::Result<EngineConfig> {
+    let Some(path) = path else {
+        return Ok(EngineConfig::default());
+    };
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("can't read config \"{}\"", path.display()))?;
+    Ok(EngineConfig::from_toml(&content)?)
+}
+
+fn run(command: Command, config: EngineConfig) -> anyhow::Result<u8> {
+    match command {
+        Command::Process(args) => process(args, config),
+        Command::Validate { input } => {
+            let report = engine::validate_transactions(File::open(input)?)?;
+            for issue in report.issues.iter() {
+                println!("{issue}");
+            }
+            if report.is_valid() {
+                println!("{} rows are correct", report.rows);
+                return Ok(exit_code::SUCCESS);
+            }
+            println!("{} problems in {} rows", report.issues.len(), report.rows);
+            Ok(exit_code::CHECK_FAILED)
+        },
+        Command::Replay(args) => replay(args, config),
+        Command::WhatIf { input, state, json } => {
+            let records = Record::from_csv(File::open(input)?)?;
+            tokio::runtime::Runtime::new()?.block_on(async {
+                let mut engine = Engine::load_state_with_config(File::open(state)?, config)?;
+                let simulation = engine.simulate(records).await?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&simulation)?);
+                } else {
+                    what_if::write_simulation(&simulation, std::io::stdout())?;
+                }
+                Ok(exit_code::SUCCESS)
+            })
+        },
+        Command::Stats { input, json } => {
+            let options = ProcessOptions { config, ..Default::default() };
+            let stats = engine::process_transactions_with_options(
+                File::open(input)?,
+                std::io::sink(),
+                &options,
+            )?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+            } else {
+                println!("{stats}");
+            }
+            Ok(exit_code::SUCCESS)
+        },
+        Command::Diff { left, right, tolerance } => {
+            let differ = diff::diff(&left, &right, tolerance, std::io::stdout())?;
+            Ok(if differ { exit_code::CHECK_FAILED } else { exit_code::SUCCESS })
+        },
+        Command::Repl { input, state } => {
+            let queue = match input {
+                Some(path) => Record::from_csv(File::open(path)?)?.into(),
+                None => Default::default(),
+            };
+            tokio::runtime::Runtime::new()?.block_on(async {
+                let engine = match state {
+                    Some(path) => Engine::load_state_with_config(File::open(path)?, config)?,
+                    None => Engine::with_config(config)?,
+                };
+                let engine = engine.with_audit_trails(AUDIT_TRAIL_LIMIT);
+                let prompt = std::io::stdin().is_terminal();
+                let input = tokio::io::BufReader::new(tokio::io::stdin());
+                repl::repl(engine, queue, input, std::io::stdout(), prompt).await?;
+                Ok(exit_code::SUCCESS)
+            })
+        },
+        Command::PrintDefaultConfig => {
+            print!("{}", EngineConfig::default().to_toml());
+            Ok(exit_code::SUCCESS)
+        },
+        Command::Serve { addr, daemon } => tokio::runtime::Runtime::new()?.block_on(async {
+            let engine = Engine::with_config(config)?.with_audit_trails(AUDIT_TRAIL_LIMIT);
+            let engine = start_daemon(engine, &daemon).await?;
+            serve::serve(addr, Arc::new(Mutex::new(engine))).await?;
+            Ok(exit_code::SUCCESS)
+        }),
+        Command::Tcp { addr, daemon } => tokio::runtime::Runtime::new()?.block_on(async {
+            let engine = start_daemon(Engine::with_config(config)?, &daemon).await?;
+            tcp::listen(addr, Arc::new(Mutex::new(engine))).await?;
+            Ok(exit_code::SUCCESS)
+        }),
+        Command::Watch { inbox, state, daemon } => {
+            tokio::runtime::Runtime::new()?.block_on(async {
+                let engine = match &state {
+                    Some(path) if path.exists() => {
+                        Engine::load_state_with_config(File::open(path)?, config)?
+                    },
+                    _ => Engine::with_config(config)?,
+                };
+                let engine = start_daemon(engine, &daemon).await?;
+                watch::watch(&inbox, engine, state.as_deref(), shutdown()).await?;
+                Ok(exit_code::SUCCESS)
+            })
+        },
+    }
+}
+
+fn process(args: ProcessArgs, config: EngineConfig) -> anyhow::Result<u8> {
+    let options = ProcessOptions { config, ..args.options()? };
+
+    if args.follow {
+        let interval = std::time::Duration::from_secs(args.interval);
+        return tokio::runtime::Runtime::new()?.block_on(async {
+            let mut engine = Engine::with_config(options.config.clone())?;
+            set_audit_log(&engine, options.audit_log.as_deref())?;
+            serve_metrics(args.metrics_addr, &engine).await?;
+            let out = std::io::stdout();
+            follow::follow(&args.input, &mut engine, &options, interval, out, shutdown()).await?;
+            report_stats(&engine.stats(), args.stats, args.stats_json.as_deref())?;
+            Ok(exit_code::SUCCESS)
+        });
+    }
+
+    let file = File::open(&args.input)?;
+    if let Some(accounts) = &args.mt940 {
+        let accounts = File::open(accounts)?;
+        let stats =
+            engine::process_mt940_transactions(file, accounts, &mut std::io::stdout(), &options)?;
+        report_stats(&stats, args.stats, args.stats_json.as_deref())?;
+        return Ok(exit_code::SUCCESS);
+    }
 
     // check if file extension is ".csv"
-    if !input_csv.ends_with(".csv") {
-        log::info!("Incorrect file extension. Extension must be \".csv\"");
+    if args.input.extension().map_or(true, |ext| ext != "csv") {
+        tracing::info!("Incorrect file extension. Extension must be \".csv\"");
+    }
+
+    if let Some(clients) = args.statement {
+        engine::export_statements(file, &mut std::io::stdout(), &clients, &options.config)?;
+    } else if let Some(clients) = args.audit {
+        engine::export_audit_trails(file, &mut std::io::stdout(), &clients, &options.config)?;
+    } else if let Some(clients) = args.camt053 {
+        let now = std::time::SystemTime::now();
+        let camt053_options = Camt053Options {
+            message_id: args.message_id.unwrap_or_else(|| {
+                let secs = now.duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs();
+                format!("STMT{secs}")
+            }),
+            created_at: humantime::format_rfc3339_seconds(now).to_string(),
+            currency: args.output.currency.unwrap_or(OutputSchema::DEFAULT_CURRENCY.to_string()),
+        };
+        let out = &mut std::io::stdout();
+        engine::export_camt053(file, out, &clients, &camt053_options, &options.config)?;
+    } else if args.dashboard {
+        let metrics = Arc::new(Metrics::default());
+        let options = ProcessOptions { metrics: Some(metrics.clone()), ..options };
+        let size = file.metadata()?.len();
+        let (dashboard, file) = Dashboard::start(file, size, metrics);
+        let stats =
+            engine::process_transactions_with_options(file, &mut std::io::stdout(), &options);
+        dashboard.finish();
+        report_stats(&stats?, args.stats, args.stats_json.as_deref())?;
+    } else {
+        let stats =
+            engine::process_transactions_with_options(file, &mut std::io::stdout(), &options)?;
+        report_stats(&stats, args.stats, args.stats_json.as_deref())?;
+    }
+
+    Ok(exit_code::SUCCESS)
+}
+
+fn replay(args: ReplayArgs, config: EngineConfig) -> anyhow::Result<u8> {
+    let options = args.output.options()?;
+    let records = Record::from_csv(File::open(&args.input)?)?;
+
+    tokio::runtime::Runtime::new()?.block_on(async {
+        let mut engine = Engine::load_state_with_config(File::open(&args.state)?, config)?;
+        set_audit_log(&engine, options.audit_log.as_deref())?;
+        for record in records {
+            engine.process_record(record).await?;
+        }
+
+        engine.write_balances(std::io::stdout(), options.order, &options.schema).await?;
+        if args.save {
+            watch::save_state(&mut engine, &args.state).await?;
+        }
+        Ok(exit_code::SUCCESS)
+    })
+}
+
+/// print summary of the run to stderr, so it doesn't mix with balances, and/or write it as json
+fn report_stats(stats: &RunStats, print: bool, json_path: Option<&Path>) -> anyhow::Result<()> {
+    if print {
+        eprintln!("{stats}");
     }
+    if let Some(path) = json_path {
+        let file = std::io::BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(file, stats)?;
+    }
+    Ok(())
+}
 
-    let file = std::fs::File::open(input_csv)?;
-    engine::process_transactions(file, &mut std::io::stdout())?;
+/// prepare engine of a long-lived mode
+async fn start_daemon(engine: Engine, args: &DaemonArgs) -> anyhow::Result<Engine> {
+    set_audit_log(&engine, args.audit_log.as_deref())?;
+    serve_metrics(args.metrics_addr, &engine).await?;
+    Ok(engine)
+}
 
+/// append changes of all wallets to the given file, if any
+fn set_audit_log(engine: &Engine, path: Option<&Path>) -> anyhow::Result<()> {
+    if let Some(path) = path {
+        engine.set_audit_log(std::fs::OpenOptions::new().create(true).append(true).open(path)?);
+    }
     Ok(())
 }
 
-fn print_help() {
-    println!("Usage:\n   cargo run -- <input.csv> > <output.csv>");
+/// expose metrics of the engine on the given address, if any
+async fn serve_metrics(addr: Option<SocketAddr>, engine: &Engine) -> anyhow::Result<()> {
+    if let Some(addr) = addr {
+        metrics::spawn(addr, engine.metrics()).await?;
+    }
+    Ok(())
 }
 
-fn report_error(msg: &str, error_code: i32) -> ! {
-    eprintln!("{}: {}", Style::new().red().bold().apply_to("ERR"), msg);
-    std::process::exit(error_code);
+async fn shutdown() {
+    let _ = tokio::signal::ctrl_c().await;
 }
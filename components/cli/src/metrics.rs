@@ -0,0 +1,33 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::State, http::header::CONTENT_TYPE, response::IntoResponse, routing::get, Router,
+};
+use engine::Metrics;
+use tokio::net::TcpListener;
+
+/// content type of Prometheus text exposition format
+const CONTENT_TYPE_PROMETHEUS: &str = "text/plain; version=0.0.4";
+
+/// serve `GET /metrics` on the given address in the background, as long as the runtime lives
+pub(crate) async fn spawn(addr: SocketAddr, metrics: Arc<Metrics>) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    tracing::info!("Metrics available on http://{}/metrics", listener.local_addr()?);
+
+    tokio::spawn(async move {
+        if let Err(e) = run(listener, metrics).await {
+            tracing::error!(error = %e, "Metrics server failed");
+        }
+    });
+    Ok(())
+}
+
+pub(crate) async fn run(listener: TcpListener, metrics: Arc<Metrics>) -> anyhow::Result<()> {
+    axum::serve(listener, Router::new().route("/metrics", get(get_metrics)).with_state(metrics))
+        .await?;
+    Ok(())
+}
+
+pub(crate) async fn get_metrics(State(metrics): State<Arc<Metrics>>) -> impl IntoResponse {
+    ([(CONTENT_TYPE, CONTENT_TYPE_PROMETHEUS)], metrics.render())
+}
@@ -0,0 +1,254 @@
+use std::{collections::VecDeque, io::Write, path::Path};
+
+use engine::{Engine, OutputOrder, OutputSchema, Record, TxType};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+const HELP: &str = "\
+Commands:
+  load <file>                   queue records of the csv file
+  step [n]                      process next n queued records (default 1)
+  run                           process all queued records
+  skip [n]                      drop next n queued records (default 1), eg. an invalid one
+  <type>,<client>,<tx>[,amount] process a single transaction, eg. `deposit,1,7,2.5`
+  wallet <client>               balance of the client
+  disputes <client>             disputes, resolves and chargebacks applied to the client wallet
+  balances                      balances of all clients
+  save <file>                   save engine state, so it can be loaded with `--state`
+  help                          print this help
+  quit                          exit";
+
+/// Interactive shell over the engine: records are queued from files and processed one by one or
+/// typed in by hand, and wallets can be inspected between them. Commands are read from `input`
+/// till it ends or `quit` is typed. Errors of a command are printed and the shell goes on
+pub(crate) async fn repl<R: AsyncBufRead + Unpin, W: Write>(
+    mut engine: Engine,
+    mut queue: VecDeque<Record>,
+    input: R,
+    mut out: W,
+    prompt: bool,
+) -> anyhow::Result<()> {
+    let mut lines = input.lines();
+    loop {
+        if prompt {
+            write!(out, "[{} queued]> ", queue.len())?;
+            out.flush()?;
+        }
+        let Some(line) = lines.next_line().await? else {
+            break;
+        };
+
+        match line.trim() {
+            "" => continue,
+            "quit" | "exit" => break,
+            line => {
+                if let Err(e) = run_command(&mut engine, &mut queue, line, &mut out).await {
+                    writeln!(out, "error: {e}")?;
+                }
+            },
+        }
+    }
+    Ok(())
+}
+
+async fn run_command<W: Write>(
+    engine: &mut Engine,
+    queue: &mut VecDeque<Record>,
+    line: &str,
+    out: &mut W,
+) -> anyhow::Result<()> {
+    if line.contains(',') {
+        return process(engine, Record::from_csv_line(line)?, out).await;
+    }
+
+    let (command, arg) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let arg = arg.trim();
+    match command {
+        "help" => writeln!(out, "{HELP}")?,
+        "load" => {
+            // invalid rows are reported and the rest is queued, but a file which can't be read
+            // queues nothing
+            let (mut records, mut invalid) = (Vec::new(), Vec::new());
+            for (line, record) in Record::csv_rows(std::fs::File::open(Path::new(arg))?)? {
+                match record {
+                    Ok(record) => records.push(record),
+                    Err(e) if e.is_invalid_row() => invalid.push((line, e)),
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            for (line, e) in invalid {
+                let line = line.map(|line| format!("line {line}: ")).unwrap_or_default();
+                writeln!(out, "{line}skipped invalid row: {e}")?;
+            }
+            writeln!(out, "queued {} records", records.len())?;
+            queue.extend(records);
+        },
+        "step" => {
+            let count = if arg.is_empty() { 1 } else { arg.parse()? };
+            for _ in 0..count {
+                let Some(record) = queue.pop_front() else {
+                    writeln!(out, "no more queued records")?;
+                    break;
+                };
+                process_queued(engine, queue, record, out).await?;
+            }
+        },
+        "run" => {
+            let count = queue.len();
+            while let Some(record) = queue.pop_front() {
+                process_queued(engine, queue, record, out).await?;
+            }
+            writeln!(out, "processed {count} records")?;
+        },
+        "skip" => {
+            let count = if arg.is_empty() { 1 } else { arg.parse()? };
+            let skipped = queue.drain(..count.min(queue.len())).count();
+            writeln!(out, "skipped {skipped} records")?;
+        },
+        "wallet" => match engine.balance(arg.parse()?).await? {
+            Some(balance) => writeln!(
+                out,
+                "available: {}, held: {}, total: {}, locked: {}",
+                balance.available, balance.held, balance.total, balance.locked
+            )?,
+            None => writeln!(out, "unknown client")?,
+        },
+        "disputes" => {
+            let Some(trail) = engine.audit_trail(arg.parse()?).await? else {
+                writeln!(out, "unknown client")?;
+                return Ok(());
+            };
+            let disputes = trail.iter().filter(|entry| {
+                matches!(entry.ty, TxType::Dispute | TxType::Resolve | TxType::Chargeback)
+            });
+            for entry in disputes {
+                let line = entry.line.map(|line| format!(" (line {line})")).unwrap_or_default();
+                writeln!(
+                    out,
+                    "{} of tx {}{line}: held {} -> {}, locked: {}",
+                    entry.ty, entry.tx, entry.before.held, entry.after.held, entry.after.locked
+                )?;
+            }
+        },
+        "balances" => {
+            engine
+                .write_balances(&mut *out, OutputOrder::ClientId, &OutputSchema::default())
+                .await?
+        },
+        "save" => engine.save_state(std::io::BufWriter::new(std::fs::File::create(arg)?)).await?,
+        _ => writeln!(out, "unknown command \"{command}\", type `help` for the list")?,
+    }
+    Ok(())
+}
+
+/// process a record taken from the queue. It goes back to the front of the queue if it can't be
+/// processed, so it's neither lost nor skipped silently
+async fn process_queued<W: Write>(
+    engine: &mut Engine,
+    queue: &mut VecDeque<Record>,
+    record: Record,
+    out: &mut W,
+) -> anyhow::Result<()> {
+    if let Err(e) = process(engine, record.clone(), out).await {
+        queue.push_front(record);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// process a single record and print what happened to it. Nothing is printed if it's invalid
+async fn process<W: Write>(engine: &mut Engine, record: Record, out: &mut W) -> anyhow::Result<()> {
+    let line = record.line.map(|line| format!("line {line}: ")).unwrap_or_default();
+    let amount = record.amount.map(|amount| format!(" {amount}")).unwrap_or_default();
+    let description =
+        format!("{line}{} client {} tx {}{amount}", record.ty, record.client_id, record.tx_id);
+
+    let outcome = engine.submit_record(record).await?;
+    match outcome.await {
+        Ok(Ok(())) => writeln!(out, "{description}: ok")?,
+        Ok(Err(rejection)) => writeln!(out, "{description}: rejected: {rejection}")?,
+        Err(_) => writeln!(out, "{description}: not processed")?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn step_through_queued_and_typed_records() {
+        let queue = Record::from_csv(
+            "type,client,tx,amount\ndeposit,1,1,2.0\nwithdrawal,1,2,5.0\ndeposit,2,3,1.0\n"
+                .as_bytes(),
+        )
+        .unwrap()
+        .into();
+        let commands = "step\nstep 5\ndispute, 1, 1\nwallet 1\ndisputes 1\nwallet 9\nfoo\nquit\n";
+
+        let mut out = Vec::new();
+        let engine = Engine::new().with_audit_trails(crate::AUDIT_TRAIL_LIMIT);
+        repl(engine, queue, commands.as_bytes(), &mut out, false).await.unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "line 2: deposit client 1 tx 1 2: ok
+line 3: withdrawal client 1 tx 2 5: rejected: insufficient available funds
+line 4: deposit client 2 tx 3 1: ok
+no more queued records
+dispute client 1 tx 1: ok
+available: 0, held: 2, total: 2, locked: false
+dispute of tx 1: held 0 -> 2, locked: false
+unknown client
+unknown command \"foo\", type `help` for the list
+"
+        );
+    }
+
+    #[tokio::test]
+    async fn load_skips_invalid_rows() {
+        let path = std::env::temp_dir().join(format!("repl_load_{}.csv", std::process::id()));
+        std::fs::write(
+            &path,
+            "type,client,tx,amount\ndeposit,1,1,1.0\n# comment\ndeposit,1,x,1.0\ndeposit,1,3,2.0\n",
+        )
+        .unwrap();
+        let commands = format!("load {}\nrun\n", path.display());
+
+        let mut out = Vec::new();
+        repl(Engine::new(), VecDeque::new(), commands.as_bytes(), &mut out, false).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "line 4: skipped invalid row: RecordError: field 2: invalid digit found in string
+queued 2 records
+line 2: deposit client 1 tx 1 1: ok
+line 5: deposit client 1 tx 3 2: ok
+processed 2 records
+"
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_queued_record_stays_queued() {
+        let queue =
+            Record::from_csv("type,client,tx,amount\ndeposit,1,1,\ndeposit,1,2,1.0\n".as_bytes())
+                .unwrap()
+                .into();
+        let commands = "step\nrun\nskip\nrun\n";
+
+        let mut out = Vec::new();
+        repl(Engine::new(), queue, commands.as_bytes(), &mut out, false).await.unwrap();
+        let error =
+            "error: RecordError: The amount field is missing for deposit transaction in csv";
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "{error}
+{error}
+skipped 1 records
+line 3: deposit client 1 tx 2 1: ok
+processed 1 records
+"
+            )
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use std::net::SocketAddr;
+
+use axum::{
+    body::Bytes,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, Query, State,
+    },
+    http::{header::CONTENT_TYPE, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use engine::{ClientId, EngineError, OutputOrder, Record, WalletEvent};
+use tokio::{
+    net::TcpListener,
+    sync::broadcast::{self, error::RecvError},
+};
+
+use crate::SharedEngine;
+
+/// serve the engine over HTTP on the given address until the process is killed
+pub(crate) async fn serve(addr: SocketAddr, engine: SharedEngine) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    tracing::info!("Listening on {}", listener.local_addr()?);
+
+    run(listener, engine).await
+}
+
+pub(crate) async fn run(listener: TcpListener, engine: SharedEngine) -> anyhow::Result<()> {
+    axum::serve(listener, router(engine)).await?;
+    Ok(())
+}
+
+fn router(engine: SharedEngine) -> Router {
+    Router::new()
+        .route("/transactions", post(post_transactions))
+        .route("/clients", get(get_clients))
+        .route("/clients/:id", get(get_client))
+        .route("/clients/:id/audit", get(get_audit_trail))
+        .route("/subscribe", get(subscribe))
+        .route("/metrics", get(get_metrics))
+        .with_state(engine)
+}
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum JsonRecords {
+    Batch(Vec<Record>),
+    Single(Record),
+}
+
+/// accepts csv (`type,client,tx,amount` with header) or json (single record or array of them)
+async fn post_transactions(
+    State(engine): State<SharedEngine>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let is_json = headers
+        .get(CONTENT_TYPE)
+        .and_then(|ty| ty.to_str().ok())
+        .is_some_and(|ty| ty.starts_with("application/json"));
+
+    let records = if is_json {
+        match serde_json::from_slice::<JsonRecords>(&body) {
+            Ok(JsonRecords::Batch(records)) => records,
+            Ok(JsonRecords::Single(record)) => vec![record],
+            Err(e) => return error_response(StatusCode::BAD_REQUEST, e.to_string()),
+        }
+    } else {
+        match Record::from_csv(body.as_ref()) {
+            Ok(records) => records,
+            Err(e) => return error_response(StatusCode::BAD_REQUEST, e.to_string()),
+        }
+    };
+
+    match engine.lock().await.process_records(records).await {
+        Ok(processed) => Json(serde_json::json!({ "processed": processed })).into_response(),
+        Err(e @ EngineError::RecordError(_)) => {
+            error_response(StatusCode::BAD_REQUEST, e.to_string())
+        },
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+async fn get_clients(State(engine): State<SharedEngine>) -> Response {
+    match engine.lock().await.balances(OutputOrder::ClientId).await {
+        Ok(balances) => Json(balances).into_response(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+async fn get_client(State(engine): State<SharedEngine>, Path(id): Path<ClientId>) -> Response {
+    match engine.lock().await.balance(id).await {
+        Ok(Some(balance)) => Json(balance).into_response(),
+        Ok(None) => error_response(StatusCode::NOT_FOUND, format!("Unknown client: {id}")),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+async fn get_metrics(State(engine): State<SharedEngine>) -> Response {
+    let metrics = engine.lock().await.metrics();
+    crate::metrics::get_metrics(State(metrics)).await.into_response()
+}
+
+async fn get_audit_trail(State(engine): State<SharedEngine>, Path(id): Path<ClientId>) -> Response {
+    match engine.lock().await.audit_trail(id).await {
+        Ok(Some(entries)) => Json(entries).into_response(),
+        Ok(None) => error_response(StatusCode::NOT_FOUND, format!("Unknown client: {id}")),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct SubscribeQuery {
+    /// subscribe to a single client, all clients if missing
+    client: Option<ClientId>,
+}
+
+/// websocket which receives json event every time wallet of the subscribed client(s) changes
+async fn subscribe(
+    State(engine): State<SharedEngine>,
+    Query(query): Query<SubscribeQuery>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let events = engine.lock().await.subscribe();
+    ws.on_upgrade(move |socket| send_events(socket, events, query.client))
+}
+
+async fn send_events(
+    mut socket: WebSocket,
+    mut events: broadcast::Receiver<WalletEvent>,
+    client: Option<ClientId>,
+) {
+    loop {
+        let msg = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) if client.is_some_and(|id| id != event.client()) => continue,
+                Ok(event) => serde_json::to_string(&event),
+                // subscriber is too slow. Let it know, so it can fetch current balances again
+                Err(RecvError::Lagged(missed)) => {
+                    serde_json::to_string(&serde_json::json!({ "missed": missed }))
+                },
+                Err(RecvError::Closed) => break,
+            },
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                // nothing is expected from the subscriber
+                Some(Ok(_)) => continue,
+            },
+        };
+
+        let Ok(msg) = msg else {
+            continue;
+        };
+        if socket.send(Message::Text(msg)).await.is_err() {
+            break;
+        }
+    }
+}
+
+fn error_response(status: StatusCode, msg: String) -> Response {
+    (status, Json(serde_json::json!({ "error": msg }))).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use engine::Engine;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        sync::Mutex,
+    };
+
+    use super::*;
+
+    async fn start_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(run(listener, Arc::new(Mutex::new(Engine::new()))));
+        addr
+    }
+
+    /// send raw HTTP/1.1 request and return status code and body of the response
+    async fn request(
+        addr: SocketAddr,
+        method: &str,
+        path: &str,
+        body: Option<(&str, &str)>,
+    ) -> (u16, String) {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+
+        let mut request =
+            format!("{method} {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n");
+        if let Some((content_type, body)) = body {
+            request += &format!(
+                "Content-Type: {content_type}\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            );
+        } else {
+            request += "\r\n";
+        }
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        let status = response[9..12].parse().unwrap();
+        let body = response.split_once("\r\n\r\n").map(|(_, body)| body).unwrap_or_default();
+        (status, body.to_string())
+    }
+
+    #[tokio::test]
+    async fn post_csv_and_json_transactions() {
+        let addr = start_server().await;
+
+        let csv = "type, client, tx, amount\ndeposit, 1, 1, 1.5\ndeposit, 2, 2, 2.0";
+        let (status, body) = request(addr, "POST", "/transactions", Some(("text/csv", csv))).await;
+        assert_eq!(status, 200);
+        assert_eq!(body, r#"{"processed":2}"#);
+
+        let json = r#"[{"type":"withdrawal","client":1,"tx":3,"amount":0.5},{"type":"dispute","client":2,"tx":2}]"#;
+        let (status, _) =
+            request(addr, "POST", "/transactions", Some(("application/json", json))).await;
+        assert_eq!(status, 200);
+
+        let json = r#"{"type":"deposit","client":3,"tx":4,"amount":1.0}"#;
+        let (status, _) =
+            request(addr, "POST", "/transactions", Some(("application/json", json))).await;
+        assert_eq!(status, 200);
+
+        let (status, body) = request(addr, "GET", "/clients", None).await;
+        assert_eq!(status, 200);
+        assert_eq!(
+            body,
+            r#"[{"client":1,"available":1.0,"held":0.0,"total":1.0,"locked":false},{"client":2,"available":0.0,"held":2.0,"total":2.0,"locked":false},{"client":3,"available":1.0,"held":0.0,"total":1.0,"locked":false}]"#
+        );
+
+        let (status, body) = request(addr, "GET", "/clients/2", None).await;
+        assert_eq!(status, 200);
+        assert_eq!(body, r#"{"client":2,"available":0.0,"held":2.0,"total":2.0,"locked":false}"#);
+    }
+
+    #[tokio::test]
+    async fn subscribe_to_wallet_changes() {
+        use futures_util::StreamExt;
+        use tokio_tungstenite::tungstenite::Message;
+
+        let addr = start_server().await;
+        let (mut socket, _) =
+            tokio_tungstenite::connect_async(format!("ws://{addr}/subscribe?client=1"))
+                .await
+                .unwrap();
+
+        let csv = "type, client, tx, amount
+deposit, 1, 1, 1.5
+deposit, 2, 2, 2.0
+withdrawal, 1, 3, 5.0
+dispute, 1, 1,
+chargeback, 1, 1,";
+        let (status, _) = request(addr, "POST", "/transactions", Some(("text/csv", csv))).await;
+        assert_eq!(status, 200);
+
+        let mut events = Vec::new();
+        while events.len() < 3 {
+            let Some(Ok(Message::Text(event))) = socket.next().await else {
+                panic!();
+            };
+            events.push(event);
+        }
+        assert_eq!(
+            events,
+            vec![
+                r#"{"tx":1,"type":"deposit","client":1,"available":1.5,"held":0.0,"total":1.5,"locked":false}"#,
+                r#"{"tx":1,"type":"dispute","client":1,"available":0.0,"held":1.5,"total":1.5,"locked":false}"#,
+                r#"{"tx":1,"type":"chargeback","client":1,"available":0.0,"held":0.0,"total":0.0,"locked":true}"#,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_batch_is_rejected_as_a_whole() {
+        let addr = start_server().await;
+
+        let csv = "type, client, tx, amount\ndeposit, 1, 1, 1.5\ndeposit, 1, 2,";
+        let (status, body) = request(addr, "POST", "/transactions", Some(("text/csv", csv))).await;
+        assert_eq!(status, 400);
+        assert_eq!(
+            body,
+            r#"{"error":"RecordError: The amount field is missing for deposit transaction in csv"}"#
+        );
+
+        let (status, _) = request(addr, "GET", "/clients/1", None).await;
+        assert_eq!(status, 404);
+    }
+}
@@ -0,0 +1,140 @@
+use std::net::SocketAddr;
+
+use engine::{Record, TxOutcome};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, oneshot},
+};
+
+use crate::SharedEngine;
+
+/// how many rows can wait for their ack before we stop reading from the connection
+const ACK_QUEUE_SIZE: usize = 1024;
+
+/// Answer for a single row, written back to the producer in the order rows came in
+enum Ack {
+    Pending(oneshot::Receiver<TxOutcome>),
+    Error(String),
+}
+
+/// accept connections streaming csv rows until the process is killed
+pub(crate) async fn listen(addr: SocketAddr, engine: SharedEngine) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    tracing::info!("Listening on {}", listener.local_addr()?);
+
+    run(listener, engine).await
+}
+
+pub(crate) async fn run(listener: TcpListener, engine: SharedEngine) -> anyhow::Result<()> {
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, engine).await {
+                tracing::warn!(%peer, error = %e, "Connection failed");
+            }
+        });
+    }
+}
+
+/// Each line is a `type,client,tx,amount` row, header and `#` comments are skipped. For each row
+/// one line is written back: `ok`, `rejected: <reason>` or `error: <message>`
+async fn handle_connection(stream: TcpStream, engine: SharedEngine) -> anyhow::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let (ack_sender, mut ack_receiver) = mpsc::channel::<Ack>(ACK_QUEUE_SIZE);
+
+    // acks are written by a separate task, so next rows can be read and sent to clients while
+    // the previous ones are still processed
+    let ack_writer = tokio::spawn(async move {
+        while let Some(ack) = ack_receiver.recv().await {
+            let line = match ack {
+                Ack::Pending(outcome) => match outcome.await {
+                    Ok(Ok(())) => "ok".to_string(),
+                    Ok(Err(rejection)) => format!("rejected: {rejection}"),
+                    Err(_) => "error: transaction was not processed".to_string(),
+                },
+                Ack::Error(msg) => format!("error: {msg}"),
+            };
+            writer.write_all(format!("{line}\n").as_bytes()).await?;
+        }
+        Ok::<_, std::io::Error>(())
+    });
+
+    let mut lines = BufReader::new(reader).lines();
+    let mut line_no = 0;
+    while let Some(line) = lines.next_line().await? {
+        line_no += 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || Record::is_csv_header(line) {
+            continue;
+        }
+
+        let ack = match Record::from_csv_line(line) {
+            Ok(record) => match engine.lock().await.submit_record(record.at_line(line_no)).await {
+                Ok(outcome) => Ack::Pending(outcome),
+                Err(e) => Ack::Error(e.to_string()),
+            },
+            Err(e) => {
+                engine.lock().await.invalid_row();
+                Ack::Error(e.to_string())
+            },
+        };
+
+        if ack_sender.send(ack).await.is_err() {
+            // writer is gone, so the producer won't get any answer anyway
+            break;
+        }
+    }
+
+    drop(ack_sender);
+    ack_writer.await??;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use engine::{Engine, OutputOrder};
+    use tokio::{io::AsyncReadExt, sync::Mutex};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn stream_rows_and_receive_acks() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let engine = Arc::new(Mutex::new(Engine::new()));
+        tokio::spawn(run(listener, engine.clone()));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let rows = "type, client, tx, amount
+deposit, 1, 1, 2.0
+withdrawal, 1, 2, 5.0
+# comment
+dispute, 1, 1
+deposit, 1, x, 1.0
+resolve, 1, 1,
+";
+        stream.write_all(rows.as_bytes()).await.unwrap();
+        stream.shutdown().await.unwrap();
+
+        let mut acks = String::new();
+        stream.read_to_string(&mut acks).await.unwrap();
+        assert_eq!(
+            acks,
+            "ok
+rejected: insufficient available funds
+ok
+error: RecordError: field 2: invalid digit found in string
+ok
+"
+        );
+        assert_eq!(engine.lock().await.metrics().stats().invalid_rows, 1);
+
+        let balances = engine.lock().await.balances(OutputOrder::ClientId).await.unwrap();
+        assert_eq!(balances.len(), 1);
+        assert_eq!(balances[0].total.to_string(), "2");
+    }
+}
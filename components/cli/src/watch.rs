@@ -0,0 +1,267 @@
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    path::{Path, PathBuf},
+};
+
+use engine::{Engine, Record, TxRejection};
+use notify::{
+    event::{AccessKind, AccessMode, ModifyKind, RenameMode},
+    EventKind, RecursiveMode, Watcher,
+};
+use tokio::sync::mpsc;
+
+const PROCESSED_DIR: &str = "processed";
+const FAILED_DIR: &str = "failed";
+
+/// Result of a single batch file, written next to the moved file as `<file>.report.json`
+#[derive(Debug, Default, serde::Serialize)]
+struct Report {
+    file: String,
+    status: &'static str,
+    rows: usize,
+    applied: usize,
+    rejected: BTreeMap<String, usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Watch `inbox` and process each csv file dropped there against the same engine.
+///
+/// Files are processed one by one in the order they arrive (files which are already in the inbox
+/// go first, oldest first). A file is processed as a whole or not at all: if any row is incorrect,
+/// nothing is applied and the file is moved to `failed/`, otherwise to `processed/`. When
+/// `state_path` is given, engine state is saved there after each file. Failures to move a file,
+/// write its report or save the state are logged and the daemon keeps watching.
+pub(crate) async fn watch(
+    inbox: &Path,
+    mut engine: Engine,
+    state_path: Option<&Path>,
+    shutdown: impl Future<Output = ()>,
+) -> anyhow::Result<()> {
+    std::fs::create_dir_all(inbox.join(PROCESSED_DIR))?;
+    std::fs::create_dir_all(inbox.join(FAILED_DIR))?;
+
+    // notify calls us from its own thread, so we pass paths to the async world through a channel
+    let (sender, mut receiver) = mpsc::unbounded_channel::<PathBuf>();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => {
+                tracing::warn!(error = %e, "Watch error");
+                return;
+            },
+        };
+
+        // we wait till a file is fully written (or moved in) before processing it
+        if matches!(
+            event.kind,
+            EventKind::Access(AccessKind::Close(AccessMode::Write))
+                | EventKind::Modify(ModifyKind::Name(RenameMode::To))
+        ) {
+            for path in event.paths {
+                let _ = sender.send(path);
+            }
+        }
+    })?;
+    watcher.watch(inbox, RecursiveMode::NonRecursive)?;
+
+    for path in existing_files(inbox)? {
+        handle_file(&mut engine, inbox, &path, state_path).await;
+    }
+
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            Some(path) = receiver.recv() => handle_file(&mut engine, inbox, &path, state_path).await,
+            _ = &mut shutdown => return Ok(()),
+        }
+    }
+}
+
+/// csv files which are already in the inbox, oldest first
+fn existing_files(inbox: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(inbox)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            files.push((entry.metadata()?.modified()?, entry.path()));
+        }
+    }
+    files.sort();
+    Ok(files.into_iter().map(|(_, path)| path).collect())
+}
+
+/// process the file, logging any failure instead of returning it. A file which is still in the
+/// inbox afterwards is moved to `failed/`, if possible
+async fn handle_file(engine: &mut Engine, inbox: &Path, path: &Path, state_path: Option<&Path>) {
+    let Err(e) = process_file(engine, inbox, path, state_path).await else {
+        return;
+    };
+    tracing::error!(path = %path.display(), error = %e, "Failed to handle file");
+
+    if let (true, Some(file_name)) = (path.is_file(), path.file_name()) {
+        if let Err(e) = std::fs::rename(path, inbox.join(FAILED_DIR).join(file_name)) {
+            tracing::error!(path = %path.display(), error = %e, "Failed to move file to failed/");
+        }
+    }
+}
+
+async fn process_file(
+    engine: &mut Engine,
+    inbox: &Path,
+    path: &Path,
+    state_path: Option<&Path>,
+) -> anyhow::Result<()> {
+    // the same file may be reported more than once, it's gone after the first time
+    if !path.is_file() || path.extension().map_or(true, |ext| ext != "csv") {
+        return Ok(());
+    }
+    let Some(file_name) = path.file_name() else {
+        return Ok(());
+    };
+    tracing::info!(path = %path.display(), "Processing file");
+
+    let mut report = Report { file: file_name.to_string_lossy().to_string(), ..Default::default() };
+    match process_records(engine, path, &mut report).await {
+        Ok(()) => report.status = PROCESSED_DIR,
+        Err(e) => {
+            tracing::warn!(path = %path.display(), error = %e, "Failed to process file");
+            report.status = FAILED_DIR;
+            report.error = Some(e.to_string());
+        },
+    }
+
+    let target = inbox.join(report.status).join(file_name);
+    std::fs::rename(path, &target)?;
+
+    let mut report_path = target.into_os_string();
+    report_path.push(".report.json");
+    std::fs::write(report_path, serde_json::to_string_pretty(&report)?)?;
+
+    if let Some(state_path) = state_path {
+        save_state(engine, state_path).await?;
+    }
+    Ok(())
+}
+
+async fn process_records(
+    engine: &mut Engine,
+    path: &Path,
+    report: &mut Report,
+) -> anyhow::Result<()> {
+    let records = Record::from_csv(std::fs::File::open(path)?)?;
+    report.rows = records.len();
+
+    for outcome in engine.submit_records(records).await? {
+        match outcome.await {
+            Ok(Ok(())) => report.applied += 1,
+            Ok(Err(rejection)) => add_rejection(report, rejection),
+            Err(_) => anyhow::bail!("Transaction was not processed"),
+        }
+    }
+    Ok(())
+}
+
+fn add_rejection(report: &mut Report, rejection: TxRejection) {
+    *report.rejected.entry(rejection.to_string()).or_default() += 1;
+}
+
+/// write state to a temporary file first, so a crash never leaves a half written state behind
+pub(crate) async fn save_state(engine: &mut Engine, state_path: &Path) -> anyhow::Result<()> {
+    let mut tmp_path = state_path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+
+    engine.save_state(std::io::BufWriter::new(std::fs::File::create(&tmp_path)?)).await?;
+    std::fs::rename(tmp_path, state_path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    /// wait till the daemon writes the file
+    async fn wait_for(path: &Path) {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !path.exists() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("{} wasn't written in time", path.display()));
+    }
+
+    #[tokio::test]
+    async fn process_dropped_files() {
+        let inbox = std::env::temp_dir().join(format!("watch_{}", std::process::id()));
+        let state_path = inbox.with_extension("state.json");
+        std::fs::create_dir_all(&inbox).unwrap();
+        std::fs::write(inbox.join("1.csv"), "type,client,tx,amount\ndeposit,1,1,2.0\n").unwrap();
+
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let watcher = tokio::spawn({
+            let (inbox, state_path) = (inbox.clone(), state_path.clone());
+            async move {
+                watch(&inbox, Engine::new(), Some(&state_path), async {
+                    let _ = stopped.await;
+                })
+                .await
+                .unwrap();
+            }
+        });
+
+        wait_for(&inbox.join("processed/1.csv.report.json")).await;
+        std::fs::write(inbox.join("2.csv"), "type,client,tx,amount\nwithdrawal,1,2,5.0\n").unwrap();
+        std::fs::write(inbox.join("3.csv"), "type,client,tx,amount\ndeposit,1,3,\n").unwrap();
+        wait_for(&inbox.join("processed/2.csv.report.json")).await;
+        wait_for(&inbox.join("failed/3.csv.report.json")).await;
+        stop.send(()).unwrap();
+        watcher.await.unwrap();
+
+        let report = |path: &str| std::fs::read_to_string(inbox.join(path)).unwrap();
+        assert!(report("processed/1.csv.report.json").contains(r#""applied": 1"#));
+        assert!(
+            report("processed/2.csv.report.json").contains(r#""insufficient available funds": 1"#)
+        );
+        assert!(report("failed/3.csv.report.json")
+            .contains("The amount field is missing for deposit transaction in csv"));
+
+        // the state survives the daemon, so the next run continues with the same balances
+        let mut engine = Engine::load_state(std::fs::File::open(&state_path).unwrap()).unwrap();
+        let balance = engine.balance(1).await.unwrap().unwrap();
+        assert_eq!(balance.total.to_string(), "2");
+
+        std::fs::remove_dir_all(&inbox).unwrap();
+        std::fs::remove_file(&state_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn keep_watching_when_state_can_not_be_saved() {
+        let inbox = std::env::temp_dir().join(format!("watch_failure_{}", std::process::id()));
+        let state_path = inbox.join("missing").join("state.json");
+        std::fs::create_dir_all(&inbox).unwrap();
+
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let watcher = tokio::spawn({
+            let inbox = inbox.clone();
+            async move {
+                watch(&inbox, Engine::new(), Some(&state_path), async {
+                    let _ = stopped.await;
+                })
+                .await
+            }
+        });
+
+        std::fs::write(inbox.join("1.csv"), "type,client,tx,amount\ndeposit,1,1,2.0\n").unwrap();
+        wait_for(&inbox.join("processed/1.csv.report.json")).await;
+        std::fs::write(inbox.join("2.csv"), "type,client,tx,amount\ndeposit,1,2,1.0\n").unwrap();
+        wait_for(&inbox.join("processed/2.csv.report.json")).await;
+        stop.send(()).unwrap();
+        assert!(watcher.await.unwrap().is_ok());
+
+        std::fs::remove_dir_all(&inbox).unwrap();
+    }
+}
@@ -0,0 +1,88 @@
+use std::io::Write;
+
+use engine::{Balance, Simulation};
+
+/// Write what the simulated batch would do: `~ id: column before -> after, ...` for changed
+/// balances, `+ id: ...` for new clients and a line per rejected transaction, followed by a
+/// summary
+pub(crate) fn write_simulation<W: Write>(
+    simulation: &Simulation,
+    mut out: W,
+) -> std::io::Result<()> {
+    for change in simulation.changes.iter() {
+        let after = columns(&change.after);
+        match &change.before {
+            Some(before) => {
+                let changes = columns(before)
+                    .into_iter()
+                    .zip(after)
+                    .filter(|(before, after)| before.1 != after.1)
+                    .map(|((column, before), (_, after))| format!("{column} {before} -> {after}"))
+                    .collect::<Vec<_>>();
+                writeln!(out, "~ {}: {}", change.client, changes.join(", "))?;
+            },
+            None => {
+                let values = after
+                    .into_iter()
+                    .map(|(column, value)| format!("{column} {value}"))
+                    .collect::<Vec<_>>();
+                writeln!(out, "+ {}: {}", change.client, values.join(", "))?;
+            },
+        }
+    }
+
+    for rejected in simulation.rejections.iter() {
+        let line = rejected.line.map(|line| format!("line {line}: ")).unwrap_or_default();
+        writeln!(
+            out,
+            "{line}{} client {} tx {}: rejected: {}",
+            rejected.ty, rejected.client, rejected.tx, rejected.rejection
+        )?;
+    }
+
+    writeln!(
+        out,
+        "{} transactions, {} rejected, {} balances changed; nothing was applied",
+        simulation.transactions,
+        simulation.rejections.len(),
+        simulation.changes.len()
+    )
+}
+
+fn columns(balance: &Balance) -> [(&'static str, String); 4] {
+    [
+        ("available", balance.available.to_string()),
+        ("held", balance.held.to_string()),
+        ("total", balance.total.to_string()),
+        ("locked", balance.locked.to_string()),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use engine::{Engine, Record};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn changed_and_new_balances_with_rejections() {
+        let mut engine = Engine::new();
+        let records = "type,client,tx,amount\ndeposit,1,1,2.0\n";
+        engine.process_records(Record::from_csv(records.as_bytes()).unwrap()).await.unwrap();
+
+        let batch = "type,client,tx,amount\ndispute,1,1,\ndeposit,2,2,1.5\nwithdrawal,2,3,9\n";
+        let simulation =
+            engine.simulate(Record::from_csv(batch.as_bytes()).unwrap()).await.unwrap();
+
+        let mut out = Vec::new();
+        write_simulation(&simulation, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "~ 1: available 2 -> 0, held 0 -> 2
++ 2: available 1.5, held 0, total 1.5, locked false
+line 4: withdrawal client 2 tx 3: rejected: insufficient available funds
+3 transactions, 1 rejected, 2 balances changed; nothing was applied
+"
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use std::{
+    io::{BufWriter, Write},
+    sync::{Mutex, OnceLock},
+};
+
+use rust_decimal::Decimal;
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+
+use crate::{
+    engine::{ClientId, TxId, TxType, Wallet},
+    EngineError,
+};
+
+/// Values of a wallet at a single point of time
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct WalletValues {
+    #[serde(with = "rust_decimal::serde::float")]
+    pub available: Decimal,
+    #[serde(with = "rust_decimal::serde::float")]
+    pub held: Decimal,
+    #[serde(with = "rust_decimal::serde::float")]
+    pub total: Decimal,
+    pub locked: bool,
+}
+
+impl WalletValues {
+    pub(crate) fn new(wallet: &Wallet, precision: u32) -> Self {
+        Self {
+            available: Wallet::round(wallet.available(), precision),
+            held: Wallet::round(wallet.held(), precision),
+            total: Wallet::round(wallet.total(), precision),
+            locked: wallet.locked(),
+        }
+    }
+}
+
+/// Single change of a client wallet, with the wallet right before and right after it
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct AuditEntry {
+    pub client: ClientId,
+    /// transaction which changed the wallet
+    pub tx: TxId,
+    /// line of the input with the transaction, if known
+    pub line: Option<u64>,
+    #[serde(rename = "type")]
+    pub ty: TxType,
+    pub before: WalletValues,
+    pub after: WalletValues,
+}
+
+impl AuditEntry {
+    /// read audit log written by the engine, one json entry per line
+    pub fn read_log<R: std::io::BufRead>(reader: R) -> Result<Vec<AuditEntry>, EngineError> {
+        let mut entries = Vec::new();
+        for (idx, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = serde_json::from_str(&line)
+                .map_err(|e| EngineError::AuditError(format!("line {}: {e}", idx + 1)))?;
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+}
+
+/// how many entries can wait for the writer before client tasks have to wait for it
+const LOG_CAPACITY: usize = 1024;
+
+enum LogMessage {
+    Entry(AuditEntry),
+    /// write everything received so far and reply once it's done
+    Flush(oneshot::Sender<()>),
+}
+
+/// Append-only log of wallet changes shared by all client tasks. Each entry is written as a single
+/// json line by a dedicated writer thread, which flushes whenever it has nothing more to write.
+/// Nothing is written till a writer is set
+#[derive(Default)]
+pub(crate) struct AuditLog {
+    sender: OnceLock<mpsc::Sender<LogMessage>>,
+    writer: Mutex<Option<JoinHandle<std::io::Result<()>>>>,
+    /// why the writer stopped, reported by every flush after that
+    error: OnceLock<String>,
+}
+
+impl AuditLog {
+    /// start the writer thread. Only the first writer is used, so it must be set once. It must be
+    /// called within tokio runtime
+    pub(crate) fn set_writer(&self, writer: Box<dyn Write + Send>) {
+        let (sender, receiver) = mpsc::channel(LOG_CAPACITY);
+        if self.sender.set(sender).is_ok() {
+            let handle = tokio::task::spawn_blocking(move || write_entries(receiver, writer));
+            *self.writer.lock().unwrap_or_else(|e| e.into_inner()) = Some(handle);
+        }
+    }
+
+    pub(crate) async fn append(&self, entry: AuditEntry) {
+        if let Some(sender) = self.sender.get() {
+            // the writer is gone only after an error, which the next flush reports
+            let _ = sender.send(LogMessage::Entry(entry)).await;
+        }
+    }
+
+    /// wait till every entry appended so far is written. Fails if the writer stopped on an error
+    pub(crate) async fn flush(&self) -> Result<(), EngineError> {
+        let Some(sender) = self.sender.get() else {
+            return Ok(());
+        };
+        let (reply, flushed) = oneshot::channel();
+        if sender.send(LogMessage::Flush(reply)).await.is_ok() && flushed.await.is_ok() {
+            return Ok(());
+        }
+
+        let handle = self.writer.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(handle) = handle {
+            let error = match handle.await {
+                Ok(Err(e)) => e.to_string(),
+                Ok(Ok(())) => "audit log closed".to_string(),
+                Err(e) => e.to_string(),
+            };
+            let _ = self.error.set(error);
+        }
+        let error = self.error.get().map_or("audit log closed", String::as_str);
+        Err(EngineError::AuditError(format!("Failed to write audit log: {error}")))
+    }
+}
+
+fn write_entries(
+    mut receiver: mpsc::Receiver<LogMessage>,
+    writer: Box<dyn Write + Send>,
+) -> std::io::Result<()> {
+    let mut out = BufWriter::new(writer);
+    while let Some(mut message) = receiver.blocking_recv() {
+        // write everything waiting in the channel before flushing
+        loop {
+            match message {
+                LogMessage::Entry(entry) => {
+                    serde_json::to_writer(&mut out, &entry)?;
+                    out.write_all(b"\n")?;
+                },
+                LogMessage::Flush(reply) => {
+                    out.flush()?;
+                    // the one who asked may be gone already, nothing to do then
+                    let _ = reply.send(());
+                },
+            }
+            match receiver.try_recv() {
+                Ok(next) => message = next,
+                Err(_) => break,
+            }
+        }
+        out.flush()?;
+    }
+    out.flush()
+}
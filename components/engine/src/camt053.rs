@@ -0,0 +1,143 @@
+use std::io::Write;
+
+use rust_decimal::Decimal;
+
+use crate::{
+    engine::{ClientId, ClientState, TxType, Wallet},
+    EngineError,
+};
+
+const NAMESPACE: &str = "urn:iso:std:iso:20022:tech:xsd:camt.053.001.02";
+
+/// Metadata of the exported ISO 20022 camt.053 (bank to customer statement) document
+#[derive(Debug, Clone)]
+pub struct Camt053Options {
+    /// unique id of the message, statement ids are derived from it
+    pub message_id: String,
+    /// creation date time of the document in ISO 8601 format, eg. 2024-04-30T12:00:00Z
+    pub created_at: String,
+    /// currency code of the funds
+    pub currency: String,
+}
+
+/// Balance entry written to the statement. The engine always starts with empty wallets, so the
+/// opening balance is zero and the closing one is the total funds after all transactions
+enum BalanceType {
+    OpeningBooked,
+    ClosingBooked,
+    ClosingAvailable,
+}
+
+impl BalanceType {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::OpeningBooked => "OPBD",
+            Self::ClosingBooked => "CLBD",
+            Self::ClosingAvailable => "CLAV",
+        }
+    }
+}
+
+pub(crate) fn write_document<W: Write>(
+    mut out: W,
+    states: &[(ClientId, ClientState)],
+    options: &Camt053Options,
+    precision: u32,
+) -> Result<(), EngineError> {
+    let message_id = escape(&options.message_id);
+    let created_at = escape(&options.created_at);
+
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(out, r#"<Document xmlns="{NAMESPACE}">"#)?;
+    writeln!(out, "  <BkToCstmrStmt>")?;
+    writeln!(out, "    <GrpHdr>")?;
+    writeln!(out, "      <MsgId>{message_id}</MsgId>")?;
+    writeln!(out, "      <CreDtTm>{created_at}</CreDtTm>")?;
+    writeln!(out, "    </GrpHdr>")?;
+    for (id, state) in states {
+        write_statement(&mut out, *id, state, options, precision)?;
+    }
+    writeln!(out, "  </BkToCstmrStmt>")?;
+    writeln!(out, "</Document>")?;
+    Ok(())
+}
+
+fn write_statement<W: Write>(
+    out: &mut W,
+    id: ClientId,
+    state: &ClientState,
+    options: &Camt053Options,
+    precision: u32,
+) -> Result<(), EngineError> {
+    let currency = escape(&options.currency);
+    let created_at = escape(&options.created_at);
+
+    writeln!(out, "    <Stmt>")?;
+    writeln!(out, "      <Id>{}-{id}</Id>", escape(&options.message_id))?;
+    writeln!(out, "      <CreDtTm>{created_at}</CreDtTm>")?;
+    writeln!(out, "      <Acct>")?;
+    writeln!(out, "        <Id><Othr><Id>{id}</Id></Othr></Id>")?;
+    writeln!(out, "        <Ccy>{currency}</Ccy>")?;
+    writeln!(out, "      </Acct>")?;
+
+    let wallet = state.wallet();
+    let (total, available) =
+        (Wallet::round(wallet.total(), precision), Wallet::round(wallet.available(), precision));
+    write_balance(out, BalanceType::OpeningBooked, Decimal::ZERO, &currency, &created_at)?;
+    write_balance(out, BalanceType::ClosingBooked, total, &currency, &created_at)?;
+    write_balance(out, BalanceType::ClosingAvailable, available, &currency, &created_at)?;
+
+    // only transactions which change the booked (total) balance are entries of the statement.
+    // Disputes and resolves just move funds between available and held
+    for entry in state.statement().iter().filter(|entry| entry.is_applied()) {
+        let (amount, indicator) = match (entry.ty(), entry.amount()) {
+            (TxType::Deposit, Some(amount)) => (amount, "CRDT"),
+            (TxType::Withdrawal | TxType::Chargeback, Some(amount)) => (amount, "DBIT"),
+            _ => continue,
+        };
+
+        writeln!(out, "      <Ntry>")?;
+        writeln!(out, "        <NtryRef>{}</NtryRef>", entry.tx_id())?;
+        writeln!(
+            out,
+            r#"        <Amt Ccy="{currency}">{}</Amt>"#,
+            Wallet::round(amount, precision)
+        )?;
+        writeln!(out, "        <CdtDbtInd>{indicator}</CdtDbtInd>")?;
+        writeln!(out, "        <Sts>BOOK</Sts>")?;
+        writeln!(out, "        <BkTxCd><Prtry><Cd>{}</Cd></Prtry></BkTxCd>", entry.ty())?;
+        writeln!(out, "        <NtryDtls><TxDtls><Refs>")?;
+        writeln!(out, "          <TxId>{}</TxId>", entry.tx_id())?;
+        writeln!(out, "        </Refs></TxDtls></NtryDtls>")?;
+        writeln!(out, "      </Ntry>")?;
+    }
+    writeln!(out, "    </Stmt>")?;
+    Ok(())
+}
+
+fn write_balance<W: Write>(
+    out: &mut W,
+    ty: BalanceType,
+    amount: Decimal,
+    currency: &str,
+    created_at: &str,
+) -> Result<(), EngineError> {
+    // camt amounts are always positive, the sign is carried by credit/debit indicator
+    let indicator = if amount < Decimal::ZERO { "DBIT" } else { "CRDT" };
+
+    writeln!(out, "      <Bal>")?;
+    writeln!(out, "        <Tp><CdOrPrtry><Cd>{}</Cd></CdOrPrtry></Tp>", ty.code())?;
+    writeln!(out, r#"        <Amt Ccy="{currency}">{}</Amt>"#, amount.abs())?;
+    writeln!(out, "        <CdtDbtInd>{indicator}</CdtDbtInd>")?;
+    writeln!(out, "        <Dt><DtTm>{created_at}</DtTm></Dt>")?;
+    writeln!(out, "      </Bal>")?;
+    Ok(())
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
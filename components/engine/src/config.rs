@@ -0,0 +1,96 @@
+use crate::EngineError;
+
+/// Amounts are stored as f32, so more decimal places would print only float noise
+const MAX_PRECISION: u32 = 8;
+/// tokio channels can't be created with more permits
+const MAX_CHANNEL_CAPACITY: usize = tokio::sync::Semaphore::MAX_PERMITS;
+
+/// Policies of the engine. Every field has a default, so a config file needs only the ones it
+/// changes
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineConfig {
+    /// transactions which can wait for a client task before the engine waits for it
+    pub channel_capacity: usize,
+    /// decimal places of amounts in all outputs
+    pub precision: u32,
+    pub disputes: DisputePolicy,
+    pub locked_accounts: LockPolicy,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            channel_capacity: 32,
+            precision: 4,
+            disputes: Default::default(),
+            locked_accounts: Default::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisputePolicy {
+    /// reject a dispute when the client has less available funds than the disputed amount.
+    /// Otherwise the funds are held anyway and available funds go negative
+    pub require_available_funds: bool,
+}
+
+impl Default for DisputePolicy {
+    fn default() -> Self {
+        Self { require_available_funds: true }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LockPolicy {
+    /// lock the account when a chargeback is applied
+    pub lock_on_chargeback: bool,
+    /// accept deposits to locked accounts. Anything else sent to a locked account is rejected
+    pub accept_deposits: bool,
+}
+
+impl Default for LockPolicy {
+    fn default() -> Self {
+        Self { lock_on_chargeback: true, accept_deposits: false }
+    }
+}
+
+impl EngineConfig {
+    /// read and validate config written in TOML
+    pub fn from_toml(s: &str) -> Result<Self, EngineError> {
+        let config: Self =
+            toml::from_str(s).map_err(|e| EngineError::ConfigError(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// config written in TOML, eg. the default one as a starting point of a config file
+    pub fn to_toml(&self) -> String {
+        // plain structs of numbers and bools can always be serialized
+        toml::to_string(self).unwrap_or_default()
+    }
+
+    pub fn validate(&self) -> Result<(), EngineError> {
+        if self.channel_capacity == 0 {
+            return Err(EngineError::ConfigError(
+                "channel_capacity must be at least 1".to_string(),
+            ));
+        }
+        if self.channel_capacity > MAX_CHANNEL_CAPACITY {
+            return Err(EngineError::ConfigError(format!(
+                "channel_capacity must be at most {MAX_CHANNEL_CAPACITY}, got {}",
+                self.channel_capacity
+            )));
+        }
+        if self.precision > MAX_PRECISION {
+            return Err(EngineError::ConfigError(format!(
+                "precision must be at most {MAX_PRECISION}, got {}",
+                self.precision
+            )));
+        }
+        Ok(())
+    }
+}
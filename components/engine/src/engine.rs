@@ -1,37 +1,404 @@
-use std::io::Write;
+use std::{
+    collections::BTreeSet,
+    io::{Read, Write},
+    sync::Arc,
+};
+
+use tokio::sync::{broadcast, oneshot};
 
-use crate::{EngineError, Record};
+use crate::{
+    audit::AuditLog, AuditEntry, Balance, EngineConfig, EngineError, Metrics, OutputOrder,
+    OutputSchema, Record, RunStats, Simulation, WalletEvent,
+};
 
 pub(crate) mod client;
 pub mod transaction;
 
-pub(crate) use client::ClientId;
-pub(crate) use transaction::TxId;
+pub use client::ClientId;
+pub(crate) use client::{ClientState, StatementEntry, Wallet};
+pub use transaction::{TxId, TxOutcome, TxRejection, TxType};
 
-use crate::engine::{
-    client::{Client, Clients},
-    transaction::TransactionInfo,
+use crate::{
+    engine::{
+        client::{Client, ClientContext, Clients},
+        transaction::TransactionInfo,
+    },
+    simulation::{BalanceChange, SimulatedRejection},
 };
 
-pub(crate) struct Engine {
+/// how many wallet events can be buffered for a subscriber before it starts missing them
+const EVENTS_CAPACITY: usize = 1024;
+
+/// version of the saved engine state format
+const STATE_VERSION: u32 = 1;
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SavedState {
+    version: u32,
+    clients: Vec<SavedClient>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SavedClient {
+    client: ClientId,
+    state: ClientState,
+}
+
+/// Transaction engine. Each client has its own task, transactions of the same client are processed
+/// in the order they are passed to the engine
+pub struct Engine {
     clients: Clients,
+    events: broadcast::Sender<WalletEvent>,
+    metrics: Arc<Metrics>,
+    audit: Arc<AuditLog>,
+    config: Arc<EngineConfig>,
+    statements: bool,
+    audit_trail: Option<usize>,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Engine {
-    pub(crate) fn new() -> Self {
-        Self { clients: Default::default() }
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(EVENTS_CAPACITY);
+        Self {
+            clients: Default::default(),
+            events,
+            metrics: Default::default(),
+            audit: Default::default(),
+            config: Default::default(),
+            statements: false,
+            audit_trail: None,
+        }
+    }
+
+    /// engine which follows the given policies
+    pub fn with_config(config: EngineConfig) -> Result<Self, EngineError> {
+        config.validate()?;
+        Ok(Self { config: Arc::new(config), ..Self::new() })
     }
 
-    pub(crate) async fn process_record(&mut self, r: Record) -> Result<(), EngineError> {
-        log::info!("{:?}", &r);
+    /// count metrics in the given handle, so they can be watched by somebody who doesn't own the
+    /// engine. It must be set before any transaction is processed
+    pub fn with_metrics(self, metrics: Arc<Metrics>) -> Self {
+        Self { metrics, ..self }
+    }
 
-        let client_id = r.client_id;
-        let tx_info = TransactionInfo::from_record(r)?;
+    /// record the statement of every client, so it can be printed with
+    /// [`Engine::print_statements`]. It must be set before any transaction is processed
+    pub(crate) fn with_statements(self) -> Self {
+        Self { statements: true, ..self }
+    }
 
-        // if client doesn't exists, create one and insert to map
-        if !self.clients.contains_key(&client_id) {
-            self.clients.insert(client_id, Client::new());
+    /// keep the latest `limit` changes of every wallet, so they can be read with
+    /// [`Engine::audit_trail`]. It must be set before any transaction is processed
+    pub fn with_audit_trails(self, limit: usize) -> Self {
+        Self { audit_trail: Some(limit), ..self }
+    }
+
+    pub fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    /// restore engine from state written by [`Engine::save_state`]. Client tasks are started
+    /// right away, so it must be called within tokio runtime
+    pub fn load_state<R: Read>(io_reader: R) -> Result<Self, EngineError> {
+        Self::load_state_with_config(io_reader, EngineConfig::default())
+    }
+
+    /// restore engine like [`Engine::load_state`], which follows the given policies from now on
+    pub fn load_state_with_config<R: Read>(
+        io_reader: R,
+        config: EngineConfig,
+    ) -> Result<Self, EngineError> {
+        let saved: SavedState = serde_json::from_reader(io_reader)
+            .map_err(|e| EngineError::StateError(e.to_string()))?;
+        if saved.version != STATE_VERSION {
+            return Err(EngineError::StateError(format!(
+                "Unsupported state version: {}. Expected {STATE_VERSION}",
+                saved.version
+            )));
+        }
+
+        let mut engine = Self::with_config(config)?;
+        for SavedClient { client, state } in saved.clients {
+            let client_task = Client::with_state(client, engine.client_context(), state);
+            engine.clients.insert(client, client_task);
+        }
+        Ok(engine)
+    }
+
+    /// save wallets and transactions history of all clients, so processing can be continued
+    /// later with [`Engine::load_state`]
+    pub async fn save_state<W: Write>(&mut self, io_writer: W) -> Result<(), EngineError> {
+        let mut states = self.snapshots().await?;
+        OutputOrder::ClientId.sort(&mut states);
+
+        let saved = SavedState {
+            version: STATE_VERSION,
+            clients: states
+                .into_iter()
+                .map(|(client, state)| SavedClient { client, state })
+                .collect(),
+        };
+        serde_json::to_writer(io_writer, &saved).map_err(|e| EngineError::StateError(e.to_string()))
+    }
+
+    /// independent copy of the given clients of the engine, with the same wallets, transactions
+    /// history and policies. Other clients are unknown to the fork. The fork has its own metrics,
+    /// events and no audit log, so nothing done to it is seen from this engine. Dropping the fork
+    /// discards it
+    pub async fn fork(&mut self, client_ids: &[ClientId]) -> Result<Self, EngineError> {
+        let mut fork = Self::with_config(self.config().clone())?;
+        for id in client_ids {
+            if fork.clients.contains_key(id) {
+                continue;
+            }
+            let Some(client) = self.clients.get_mut(id) else {
+                continue;
+            };
+            let client_task =
+                Client::with_state(*id, fork.client_context(), client.snapshot().await?);
+            fork.clients.insert(*id, client_task);
+        }
+        Ok(fork)
+    }
+
+    /// Find out what the batch of records would do without applying it: balances which would
+    /// change and transactions which would be rejected. The batch is processed by a fork of the
+    /// clients it refers to, which is discarded afterwards. Records are validated first like in
+    /// [`Engine::process_records`]
+    pub async fn simulate(&mut self, records: Vec<Record>) -> Result<Simulation, EngineError> {
+        let client_ids = records
+            .iter()
+            .map(|r| r.client_id)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let mut fork = self.fork(&client_ids).await?;
+        let transactions = fork.validate_records(records)?;
+
+        let mut submitted = Vec::with_capacity(transactions.len());
+        for (client, tx_info) in transactions {
+            let (tx, line, ty) = (tx_info.id(), tx_info.line(), tx_info.tx().ty());
+            let (tx_info, outcome) = tx_info.with_outcome();
+            fork.process_transaction(client, tx_info).await?;
+            submitted.push((client, tx, line, ty, outcome));
+        }
+
+        let mut simulation = Simulation { transactions: submitted.len(), ..Default::default() };
+        for (client, tx, line, ty, outcome) in submitted {
+            let outcome = outcome.await.map_err(|_| EngineError::FailedToGetWallet)?;
+            if let (Err(rejection), Some(ty)) = (outcome, ty) {
+                simulation.rejections.push(SimulatedRejection { client, tx, line, ty, rejection });
+            }
+        }
+
+        for after in fork.balances(OutputOrder::ClientId).await? {
+            let before = self.balance(after.client).await?;
+            if before.as_ref() != Some(&after) {
+                let client = after.client;
+                simulation.changes.push(BalanceChange { client, before, after });
+            }
+        }
+        Ok(simulation)
+    }
+
+    /// metrics of the engine. They are updated as transactions are processed, so the handle can
+    /// be kept and rendered at any time
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    /// append every following change of any wallet to `writer`, one json [`AuditEntry`] per line.
+    /// Entries are written by a separate thread, so it must be called within tokio runtime, and
+    /// only once. Write errors are returned by the next call which reads the wallets
+    pub fn set_audit_log<W: Write + Send + 'static>(&self, writer: W) {
+        self.audit.set_writer(Box::new(writer));
+    }
+
+    /// latest changes of the client wallet kept as set by [`Engine::with_audit_trails`], none if
+    /// it wasn't set. `None` if the client never sent any transaction
+    pub async fn audit_trail(
+        &mut self,
+        client_id: ClientId,
+    ) -> Result<Option<Vec<AuditEntry>>, EngineError> {
+        let Some(client) = self.clients.get_mut(&client_id) else {
+            return Ok(None);
+        };
+        Ok(Some(client.snapshot().await?.audit().cloned().collect()))
+    }
+
+    /// count a row of the input which couldn't even be read as a record as invalid, like invalid
+    /// records passed to [`Engine::process_record`]
+    pub fn invalid_row(&self) {
+        self.metrics.record(None);
+    }
+
+    /// summary of the run so far. Transactions still waiting in client queues are not counted yet
+    pub fn stats(&self) -> RunStats {
+        self.metrics.stats()
+    }
+
+    /// subscribe to wallet changes of all clients. Events of a single client come in the order
+    /// its transactions were applied
+    pub fn subscribe(&self) -> broadcast::Receiver<WalletEvent> {
+        self.events.subscribe()
+    }
+
+    pub async fn process_record(&mut self, r: Record) -> Result<(), EngineError> {
+        let (client_id, tx_info) = self.accept_record(r)?;
+        self.process_transaction(client_id, tx_info).await
+    }
+
+    /// process record like [`Engine::process_record`]. The returned receiver gets the outcome of
+    /// the transaction once the client task applies it
+    pub async fn submit_record(
+        &mut self,
+        r: Record,
+    ) -> Result<oneshot::Receiver<TxOutcome>, EngineError> {
+        let (client_id, tx_info) = self.accept_record(r)?;
+        let (tx_info, outcome) = tx_info.with_outcome();
+        self.process_transaction(client_id, tx_info).await?;
+        Ok(outcome)
+    }
+
+    /// process batch of records. Records are validated first, so either all of them are sent to
+    /// clients or none. Returns number of processed records
+    pub async fn process_records(&mut self, records: Vec<Record>) -> Result<usize, EngineError> {
+        let transactions = self.validate_records(records)?;
+
+        let count = transactions.len();
+        for (client_id, tx_info) in transactions {
+            self.process_transaction(client_id, tx_info).await?;
+        }
+        Ok(count)
+    }
+
+    /// process batch of records like [`Engine::process_records`]. Returned receivers get outcomes
+    /// of the transactions, in the same order as records
+    pub async fn submit_records(
+        &mut self,
+        records: Vec<Record>,
+    ) -> Result<Vec<oneshot::Receiver<TxOutcome>>, EngineError> {
+        let transactions = self.validate_records(records)?;
+
+        let mut outcomes = Vec::with_capacity(transactions.len());
+        for (client_id, tx_info) in transactions {
+            let (tx_info, outcome) = tx_info.with_outcome();
+            self.process_transaction(client_id, tx_info).await?;
+            outcomes.push(outcome);
+        }
+        Ok(outcomes)
+    }
+
+    fn validate_records(
+        &self,
+        records: Vec<Record>,
+    ) -> Result<Vec<(ClientId, TransactionInfo)>, EngineError> {
+        let transactions = records
+            .into_iter()
+            .map(|r| Ok((r.client_id, TransactionInfo::from_record(r)?)))
+            .collect::<Result<Vec<_>, EngineError>>()
+            .inspect_err(|_| self.metrics.record(None))?;
+
+        for (_, tx_info) in transactions.iter() {
+            self.metrics.record(tx_info.tx().ty());
         }
+        Ok(transactions)
+    }
+
+    /// convert record to transaction and count it in the metrics
+    fn accept_record(&self, r: Record) -> Result<(ClientId, TransactionInfo), EngineError> {
+        let (client_id, tx_id, line) = (r.client_id, r.tx_id, r.line);
+        match TransactionInfo::from_record(r) {
+            Ok(tx_info) => {
+                tx_info.span().in_scope(|| tracing::debug!("Transaction accepted"));
+                self.metrics.record(tx_info.tx().ty());
+                Ok((client_id, tx_info))
+            },
+            Err(e) => {
+                tracing::warn!(client = client_id, tx = tx_id, line, error = %e, "Invalid record");
+                self.metrics.record(None);
+                Err(e)
+            },
+        }
+    }
+
+    /// current balance of the client, `None` if the client never sent any transaction
+    pub async fn balance(&mut self, client_id: ClientId) -> Result<Option<Balance>, EngineError> {
+        let Some(client) = self.clients.get_mut(&client_id) else {
+            return Ok(None);
+        };
+        let state = client.snapshot().await?;
+        Ok(Some(Balance::new(client_id, &state, self.config.precision)))
+    }
+
+    /// current balances of all clients. Clients keep working, so this can be called at any time
+    pub async fn balances(&mut self, order: OutputOrder) -> Result<Vec<Balance>, EngineError> {
+        let mut states = self.snapshots().await?;
+        order.sort(&mut states);
+
+        let precision = self.config.precision;
+        Ok(states.iter().map(|(id, state)| Balance::new(*id, state, precision)).collect())
+    }
+
+    /// write current balances of all clients, the same way they are printed at the end of
+    /// processing. Clients keep working, so this can be called at any time
+    pub async fn write_balances<W: Write>(
+        &mut self,
+        out: W,
+        order: OutputOrder,
+        schema: &OutputSchema,
+    ) -> Result<(), EngineError> {
+        let states = self.snapshots().await?;
+        self.write_states(out, states, order, schema)
+    }
+
+    pub(crate) async fn snapshots(&mut self) -> Result<Vec<(ClientId, ClientState)>, EngineError> {
+        let mut states = Vec::with_capacity(self.clients.len());
+        for (id, client) in self.clients.iter_mut() {
+            states.push((*id, client.snapshot().await?));
+        }
+        self.audit.flush().await?;
+        Ok(states)
+    }
+
+    fn client_context(&self) -> ClientContext {
+        ClientContext {
+            events: self.events.clone(),
+            metrics: self.metrics.clone(),
+            audit: self.audit.clone(),
+            config: self.config.clone(),
+            statements: self.statements,
+            audit_trail: self.audit_trail,
+        }
+    }
+
+    async fn process_transaction(
+        &mut self,
+        client_id: ClientId,
+        tx_info: TransactionInfo,
+    ) -> Result<(), EngineError> {
+        // if client doesn't exists, create one and insert to map
+        let (events, metrics, audit, config) =
+            (&self.events, &self.metrics, &self.audit, &self.config);
+        let (statements, audit_trail) = (self.statements, self.audit_trail);
+        self.clients.entry(client_id).or_insert_with(|| {
+            let context = ClientContext {
+                events: events.clone(),
+                metrics: metrics.clone(),
+                audit: audit.clone(),
+                config: config.clone(),
+                statements,
+                audit_trail,
+            };
+            Client::new(client_id, context)
+        });
 
         let client =
             self.clients.get_mut(&client_id).ok_or(EngineError::ClientNotExists(client_id))?;
@@ -40,40 +407,62 @@ impl Engine {
         Ok(())
     }
 
-    pub(crate) async fn print_wallets<W: Write>(&mut self, mut out: W) -> Result<(), EngineError> {
-        #[cfg(test)]
-        {
-            self.print_sorted_wallets(out).await?;
-            return Ok(());
+    pub(crate) async fn print_wallets<W: Write>(
+        &mut self,
+        out: W,
+        order: OutputOrder,
+        schema: &OutputSchema,
+    ) -> Result<(), EngineError> {
+        let mut states = Vec::with_capacity(self.clients.len());
+        for (id, client) in self.clients.iter_mut() {
+            states.push((*id, client.state().await?));
         }
+        self.audit.flush().await?;
+        self.write_states(out, states, order, schema)
+    }
 
-        writeln!(out, "client,available,held,total")?;
-        for (id, client) in self.clients.iter_mut() {
-            let wallet = client.wallet().await?;
-            writeln!(out, "{id},{wallet}")?;
+    fn write_states<W: Write>(
+        &self,
+        mut out: W,
+        mut states: Vec<(ClientId, ClientState)>,
+        order: OutputOrder,
+        schema: &OutputSchema,
+    ) -> Result<(), EngineError> {
+        order.sort(&mut states);
+
+        writeln!(out, "{}", schema.header())?;
+        for (id, state) in states.iter() {
+            writeln!(out, "{}", schema.row(*id, state, self.config.precision))?;
         }
         Ok(())
     }
 
-    #[cfg(test)]
-    pub(crate) async fn print_sorted_wallets<W: Write>(
+    /// print every transaction of the given clients, in order, with the wallet after each of them
+    pub(crate) async fn print_statements<W: Write>(
         &mut self,
         mut out: W,
+        client_ids: &[ClientId],
     ) -> Result<(), EngineError> {
-        use std::collections::BTreeSet;
-
-        write!(out, "client,available,held,total")?;
-
-        //i need to sort these in case of testing
-        let mut sorted_clients: BTreeSet<String> = BTreeSet::new();
-        for (id, client) in self.clients.iter_mut() {
-            let wallet = client.wallet().await?;
-            sorted_clients.insert(format!("\n{id},{wallet}"));
+        writeln!(out, "{}", StatementEntry::HEADER)?;
+        for (id, state) in self.client_states(client_ids).await? {
+            for entry in state.statement() {
+                writeln!(out, "{id},{}", entry.row(self.config.precision))?;
+            }
         }
+        Ok(())
+    }
 
-        for str in sorted_clients.iter() {
-            write!(out, "{}", str)?;
+    /// finish processing of the given clients and return their states in the same order
+    pub(crate) async fn client_states(
+        &mut self,
+        client_ids: &[ClientId],
+    ) -> Result<Vec<(ClientId, ClientState)>, EngineError> {
+        let mut states = Vec::with_capacity(client_ids.len());
+        for id in client_ids {
+            let client = self.clients.get_mut(id).ok_or(EngineError::ClientNotExists(*id))?;
+            states.push((*id, client.state().await?));
         }
-        Ok(())
+        self.audit.flush().await?;
+        Ok(states)
     }
 }
@@ -1,126 +1,325 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
 
+pub(crate) use statement::StatementEntry;
 use tokio::{
     sync::{
-        mpsc,
+        broadcast, mpsc,
         mpsc::{error::SendError, Receiver, Sender},
     },
     task::JoinHandle,
 };
-use wallet::Wallet;
+use tracing::Instrument;
+pub(crate) use wallet::Wallet;
 
 use crate::{
-    engine::transaction::{TransactionInfo, Transactions, TxAction, TxResult},
-    EngineError,
+    audit::{AuditEntry, AuditLog, WalletValues},
+    engine::transaction::{
+        TransactionInfo, Transactions, TxAction, TxId, TxOutcome, TxRejection, TxResult,
+    },
+    metrics::{ClientMetrics, Metrics},
+    EngineConfig, EngineError, WalletEvent,
 };
 
+mod statement;
 mod wallet;
 
-pub(crate) type ClientId = u32;
+pub type ClientId = u32;
 // unordered map is the best option. We don't need have it sorted
 pub(super) type Clients = HashMap<ClientId, Client>;
 
 pub(super) struct Client {
     sender: Sender<TransactionInfo>,
-    join_handle: Option<JoinHandle<Wallet>>,
+    join_handle: Option<JoinHandle<ClientState>>,
+    metrics: Arc<ClientMetrics>,
+}
+
+/// Everything the engine shares with all client tasks
+#[derive(Clone)]
+pub(super) struct ClientContext {
+    pub(super) events: broadcast::Sender<WalletEvent>,
+    pub(super) metrics: Arc<Metrics>,
+    pub(super) audit: Arc<AuditLog>,
+    pub(super) config: Arc<EngineConfig>,
+    /// whether new clients record their statement
+    pub(super) statements: bool,
+    /// how many latest changes of the wallet every client keeps, if any
+    pub(super) audit_trail: Option<usize>,
+}
+
+/// Everything a client task knows about its client once all transactions are processed
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+pub(crate) struct ClientState {
+    wallet: Wallet,
+    tx_history: Transactions,
+    last_tx: Option<TxId>,
+    /// recorded only when the engine is asked for statements, and never saved
+    #[serde(skip)]
+    statement: Option<Vec<StatementEntry>>,
+    /// latest changes of the wallet, kept only when the engine is asked for audit trails, and
+    /// never saved
+    #[serde(skip)]
+    audit: Option<VecDeque<AuditEntry>>,
+}
+
+impl ClientState {
+    pub(crate) fn wallet(&self) -> &Wallet {
+        &self.wallet
+    }
+
+    /// id of the last transaction which actually changed the wallet
+    pub(crate) fn last_tx(&self) -> Option<TxId> {
+        self.last_tx
+    }
+
+    pub(crate) fn open_disputes(&self) -> usize {
+        self.tx_history.values().filter(|tx| matches!(tx, TxResult::Disputed(_))).count()
+    }
+
+    /// every transaction sent to the client, in order, with the wallet after each of them. Empty
+    /// unless the engine records statements
+    pub(crate) fn statement(&self) -> &[StatementEntry] {
+        self.statement.as_deref().unwrap_or_default()
+    }
+
+    /// latest changes of the wallet, in order. Empty unless the engine keeps audit trails
+    pub(crate) fn audit(&self) -> impl Iterator<Item = &AuditEntry> {
+        self.audit.iter().flatten()
+    }
+
+    /// apply transaction and record it in the statement. Returns the outcome with the amount of
+    /// the transaction, or the amount moved by the dispute, resolve or chargeback
+    fn process(
+        &mut self,
+        mut tx_info: TransactionInfo,
+        config: &EngineConfig,
+    ) -> (TxOutcome, Option<f32>) {
+        let result = self.apply(&tx_info, config);
+        match &result {
+            Ok(_) => {
+                tracing::debug!("Transaction applied");
+                self.last_tx = Some(tx_info.id());
+            },
+            Err(rejection) => {
+                // the transaction span exists only with debug traces, so the warning names it
+                let (tx, line, reason) = (tx_info.id(), tx_info.line(), rejection.code());
+                tracing::warn!(tx, line, reason, "Transaction rejected")
+            },
+        }
+
+        let amount = match (tx_info.tx(), &result) {
+            (TxAction::Deposit(amount) | TxAction::Withdrawal(amount), _) => Some(*amount),
+            (_, Ok(amount)) => *amount,
+            (_, Err(_)) => None,
+        };
+
+        let outcome = result.map(|_| ());
+        if let Some(reply) = tx_info.take_outcome() {
+            // the one who asked may be gone already, nothing to do then
+            let _ = reply.send(outcome);
+        }
+
+        if let (Some(ty), Some(statement)) = (tx_info.tx().ty(), self.statement.as_mut()) {
+            statement.push(StatementEntry::new(
+                tx_info.id(),
+                ty,
+                amount,
+                outcome.err(),
+                self.wallet.clone(),
+            ));
+        }
+        (outcome, amount)
+    }
+
+    /// apply transaction to the wallet. Returns amount of funds which were moved by the dispute,
+    /// resolve or chargeback
+    fn apply(
+        &mut self,
+        tx_info: &TransactionInfo,
+        config: &EngineConfig,
+    ) -> Result<Option<f32>, TxRejection> {
+        let is_deposit = matches!(tx_info.tx(), TxAction::Deposit(_));
+        if self.wallet.locked() && !(is_deposit && config.locked_accounts.accept_deposits) {
+            // other actions should be skipped till to unlocking client
+            return Err(TxRejection::AccountLocked);
+        }
+
+        match tx_info.tx() {
+            TxAction::Deposit(amount) => {
+                self.wallet.deposit(*amount)?;
+                self.tx_history.insert(tx_info.id(), TxResult::Deposited(*amount));
+                Ok(None)
+            },
+            TxAction::Withdrawal(amount) => {
+                self.wallet.withdrawal(*amount)?;
+                Ok(None)
+            },
+            TxAction::Dispute => {
+                let Some(deposit_tx) = self.tx_history.get(&tx_info.id()) else {
+                    return Err(TxRejection::UnknownTx);
+                };
+
+                let TxResult::Deposited(amount) = *deposit_tx else {
+                    return Err(TxRejection::AlreadyDisputed);
+                };
+
+                self.wallet.dispute(amount, config.disputes.require_available_funds)?;
+
+                // instead of deleting the deposited transaction, we simply insert a new
+                // disputed transaction that replaces the old one
+                self.tx_history.insert(tx_info.id(), TxResult::Disputed(amount));
+                Ok(Some(amount))
+            },
+            TxAction::Resolve => {
+                let Some(disputed_tx) = self.tx_history.get(&tx_info.id()) else {
+                    return Err(TxRejection::UnknownTx);
+                };
+
+                let TxResult::Disputed(amount) = *disputed_tx else {
+                    return Err(TxRejection::NotDisputed);
+                };
+
+                self.wallet.resolve(amount)?;
+
+                // instead of deleting the disputed transaction, we simply insert a new
+                // deposited transaction that replaces the old one
+                self.tx_history.insert(tx_info.id(), TxResult::Deposited(amount));
+                Ok(Some(amount))
+            },
+            TxAction::Chargeback => {
+                let Some(disputed_tx) = self.tx_history.get(&tx_info.id()) else {
+                    return Err(TxRejection::UnknownTx);
+                };
+
+                let TxResult::Disputed(amount) = *disputed_tx else {
+                    return Err(TxRejection::NotDisputed);
+                };
+
+                self.wallet.chargeback(amount, config.locked_accounts.lock_on_chargeback)?;
+
+                // we can't simply replace a dispute transaction with another in this case,
+                // because charge back revert an transaction. Therefore we remove it from history
+                self.tx_history.remove(&tx_info.id());
+                Ok(Some(amount))
+            },
+            TxAction::Close | TxAction::Snapshot(_) => Ok(None),
+        }
+    }
 }
 
 impl Client {
-    pub(super) fn new() -> Self {
-        let (tx, rx) = mpsc::channel::<TransactionInfo>(32);
+    pub(super) fn new(id: ClientId, context: ClientContext) -> Self {
+        let state =
+            ClientState { statement: context.statements.then(Vec::new), ..Default::default() };
+        Self::with_state(id, context, state)
+    }
+
+    /// start client task which continues from previously saved state
+    pub(super) fn with_state(id: ClientId, context: ClientContext, mut state: ClientState) -> Self {
+        if context.audit_trail.is_some() {
+            state.audit.get_or_insert_with(Default::default);
+        }
+        let (tx, rx) = mpsc::channel::<TransactionInfo>(context.config.channel_capacity);
 
-        let mut client = Self { sender: tx, join_handle: None };
+        let metrics = &context.metrics;
+        metrics.restored(state.open_disputes(), state.wallet.locked());
+        let mut client = Self { sender: tx, join_handle: None, metrics: metrics.client(id) };
 
         // we want process transactions for each client separately. It allows as to parallelize work
         // and get better performance. There are disadvantages (eg. each tokio task must work till to
         // finish of program, regardless if do something or not) but it can be easy mitigated (eg.
         // add timeout to receiver and then save a state, and start only when new transaction is sent)
-        client.run(rx);
+        client.run(id, rx, context, state);
         client
     }
 
-    fn run(&mut self, mut receiver: Receiver<TransactionInfo>) {
-        let handle = tokio::spawn(async move {
-            let mut wallet = Wallet::default();
-            let mut tx_history = Transactions::default();
-
-            // messages are received till to TxAction::Close message. Then task return wallet
-            while let Some(tx_info) = receiver.recv().await {
-                if wallet.locked() {
-                    // only close action works. Other should be skipped till to unlocking client
-                    if let TxAction::Close = tx_info.tx() {
-                        receiver.close();
+    fn run(
+        &mut self,
+        id: ClientId,
+        mut receiver: Receiver<TransactionInfo>,
+        context: ClientContext,
+        mut state: ClientState,
+    ) {
+        let ClientContext { events, metrics, audit, config, audit_trail, .. } = context;
+        let client_metrics = self.metrics.clone();
+        let handle = tokio::spawn(
+            async move {
+                // messages are received till to TxAction::Close message. Then task return the state
+                while let Some(tx_info) = receiver.recv().await {
+                    client_metrics.received();
+
+                    match tx_info.tx() {
+                        TxAction::Close => receiver.close(),
+                        TxAction::Snapshot(_) => {
+                            if let TxAction::Snapshot(reply) = tx_info.into_tx() {
+                                // the one who asked may be gone already, nothing to do then
+                                let _ = reply.send(state.clone());
+                            }
+                        },
+                        _ => {
+                            let (tx_id, ty) = (tx_info.id(), tx_info.tx().ty());
+                            let (created_at, line) = (tx_info.created_at(), tx_info.line());
+                            let span =
+                                (!tx_info.span().is_disabled()).then(|| tx_info.span().clone());
+                            let before = WalletValues::new(state.wallet(), config.precision);
+                            let was_locked = state.wallet().locked();
+                            let (outcome, amount) = match span {
+                                Some(span) => span.in_scope(|| state.process(tx_info, &config)),
+                                None => state.process(tx_info, &config),
+                            };
+                            let Some(ty) = ty else {
+                                continue;
+                            };
+
+                            if outcome.is_ok() {
+                                let after = WalletValues::new(state.wallet(), config.precision);
+                                let entry =
+                                    AuditEntry { client: id, tx: tx_id, line, ty, before, after };
+                                if let (Some(trail), Some(limit)) =
+                                    (state.audit.as_mut(), audit_trail)
+                                {
+                                    if trail.len() >= limit {
+                                        trail.pop_front();
+                                    }
+                                    trail.push_back(entry.clone());
+                                }
+                                audit.append(entry).await;
+                            }
+
+                            let latency = created_at.elapsed();
+                            let locked = !was_locked && state.wallet().locked();
+                            metrics.processed(
+                                &client_metrics,
+                                ty,
+                                outcome,
+                                amount,
+                                locked,
+                                latency,
+                            );
+                            if outcome.is_ok() && events.receiver_count() > 0 {
+                                // subscribers may unsubscribe in the meantime, it's fine to lose
+                                // the event then
+                                let _ = events.send(WalletEvent::new(
+                                    id,
+                                    tx_id,
+                                    ty,
+                                    &state,
+                                    config.precision,
+                                ));
+                            }
+                        },
                     }
-                    continue;
                 }
 
-                match tx_info.tx() {
-                    TxAction::Deposit(amount) => {
-                        wallet.deposit(*amount);
-                        tx_history.insert(tx_info.id(), TxResult::Deposited(*amount));
-                    },
-                    TxAction::Withdrawal(amount) => {
-                        wallet.withdrawal(*amount);
-                    },
-                    TxAction::Dispute => {
-                        let Some(deposit_tx) = tx_history.get(&tx_info.id()) else {
-                            log::warn!("There is no saved transaction with id: {}", tx_info.id());
-                            continue;
-                        };
-
-                        let TxResult::Deposited(amount) = deposit_tx else {
-                            log::warn!("There is no deposited transaction to dispute");
-                            continue;
-                        };
-
-                        if wallet.dispute(*amount) {
-                            // instead of deleting the deposited transaction, we simply insert a new
-                            // disputed transaction that replaces the old one
-                            tx_history.insert(tx_info.id(), TxResult::Disputed(*amount));
-                        }
-                    },
-                    TxAction::Resolve => {
-                        let Some(disputed_tx) = tx_history.get(&tx_info.id()) else {
-                            log::warn!("There is no saved transaction with id: {}", tx_info.id());
-                            continue;
-                        };
-
-                        let TxResult::Disputed(amount) = disputed_tx else {
-                            log::warn!("There is no disputed transaction to resolve");
-                            continue;
-                        };
-
-                        wallet.resolve(*amount);
-
-                        // instead of deleting the disputed transaction, we simply insert a new
-                        // deposited transaction that replaces the old one
-                        tx_history.insert(tx_info.id(), TxResult::Deposited(*amount));
-                    },
-                    TxAction::Chargeback => {
-                        let Some(disputed_tx) = tx_history.get(&tx_info.id()) else {
-                            log::warn!("There is no saved transaction with id: {}", tx_info.id());
-                            continue;
-                        };
-
-                        let TxResult::Disputed(amount) = disputed_tx else {
-                            log::warn!("There is no disputed transaction to chargeback");
-                            continue;
-                        };
-
-                        wallet.chargeback(*amount);
-
-                        // we can't simply replace a dispute transaction with another in this case,
-                        // because charge back revert an transaction. Therefore we remove it from history
-                        tx_history.remove(&tx_info.id());
-                    },
-                    TxAction::Close => receiver.close(),
-                }
+                // Once all operations are completed, return the state (wallet and history)
+                // that represent the client's transaction status
+                tracing::debug!("Client closed");
+                state
             }
-
-            // Once all operations are completed, return the wallet
-            // that represent the client's transaction status
-            wallet
-        });
+            .instrument(tracing::error_span!("client", client = id)),
+        );
 
         self.join_handle = Some(handle)
     }
@@ -129,20 +328,31 @@ impl Client {
         &mut self,
         tx_info: TransactionInfo,
     ) -> Result<(), SendError<TransactionInfo>> {
-        self.sender.send(tx_info).await?;
+        self.metrics.queued();
+        if let Err(e) = self.sender.send(tx_info).await {
+            self.metrics.received();
+            return Err(e);
+        }
         Ok(())
     }
 
-    pub(super) async fn wallet(&mut self) -> Result<Wallet, EngineError> {
+    pub(super) async fn state(&mut self) -> Result<ClientState, EngineError> {
         self.close().await?;
-        if let Some(wallet) = &mut self.join_handle {
-            Ok(wallet.await?)
+        if let Some(state) = &mut self.join_handle {
+            Ok(state.await?)
         } else {
             // this should not happen. unreachable! or error?
             Err(EngineError::FailedToGetWallet)
         }
     }
 
+    /// current state of the client. Unlike [`Client::state`] the client keeps working
+    pub(super) async fn snapshot(&mut self) -> Result<ClientState, EngineError> {
+        let (tx_info, reply) = TransactionInfo::snapshot();
+        self.process_transaction(tx_info).await?;
+        reply.await.map_err(|_| EngineError::FailedToGetWallet)
+    }
+
     pub(super) async fn close(&mut self) -> Result<(), SendError<TransactionInfo>> {
         // the easiest solution to close wallet computation is to send proper message
         self.process_transaction(TransactionInfo::close()).await?;
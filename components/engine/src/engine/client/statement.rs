@@ -0,0 +1,59 @@
+use crate::engine::{
+    client::Wallet,
+    transaction::{TxRejection, TxType},
+    TxId,
+};
+
+/// Single line of client statement: transaction sent to the client and the wallet right after it
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub(crate) struct StatementEntry {
+    tx_id: TxId,
+    ty: TxType,
+    amount: Option<f32>,
+    rejection: Option<TxRejection>,
+    wallet: Wallet,
+}
+
+impl StatementEntry {
+    pub(crate) const HEADER: &'static str =
+        "client,tx,type,amount,available,held,total,locked,status,reason";
+
+    pub(super) fn new(
+        tx_id: TxId,
+        ty: TxType,
+        amount: Option<f32>,
+        rejection: Option<TxRejection>,
+        wallet: Wallet,
+    ) -> Self {
+        Self { tx_id, ty, amount, rejection, wallet }
+    }
+
+    pub(crate) fn tx_id(&self) -> TxId {
+        self.tx_id
+    }
+
+    pub(crate) fn ty(&self) -> TxType {
+        self.ty
+    }
+
+    pub(crate) fn amount(&self) -> Option<f32> {
+        self.amount
+    }
+
+    pub(crate) fn is_applied(&self) -> bool {
+        self.rejection.is_none()
+    }
+
+    /// statement row without the client column, amounts rounded to `precision` decimal places
+    pub(crate) fn row(&self, precision: u32) -> String {
+        let amount = self
+            .amount
+            .map(|amount| Wallet::round(amount, precision).to_string())
+            .unwrap_or_default();
+        let status = match &self.rejection {
+            None => "applied,".to_string(),
+            Some(rejection) => format!("rejected,{rejection}"),
+        };
+        format!("{},{},{amount},{},{status}", self.tx_id, self.ty, self.wallet.row(precision))
+    }
+}
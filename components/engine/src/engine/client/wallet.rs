@@ -1,7 +1,8 @@
-use std::fmt::{Display, Formatter};
-
 use rust_decimal::prelude::*;
 
+use crate::engine::TxRejection;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub(crate) struct Wallet {
     available: f32,
     held: f32,
@@ -14,82 +15,102 @@ impl Default for Wallet {
         Self { available: 0.0, held: 0.0, total: 0.0, locked: false }
     }
 }
-impl Display for Wallet {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        //write!(f, "{:.4},{:.4},{:.4},{:.4}", self.available, self.held, self.total, self.locked)
+impl Wallet {
+    /// largest amount a wallet can hold, so it's still finite and can be printed as a decimal
+    pub(crate) const MAX_AMOUNT: f32 = 7.9e28;
 
+    /// `available,held,total,locked` with amounts rounded to `precision` decimal places
+    pub(crate) fn row(&self, precision: u32) -> String {
         //we can use rust_decimal crate to round and display it without trailing zeros
-        write!(
-            f,
+        format!(
             "{},{},{},{}",
-            Self::round(self.available),
-            Self::round(self.held),
-            Self::round(self.total),
+            Self::round(self.available, precision),
+            Self::round(self.held, precision),
+            Self::round(self.total, precision),
             self.locked
         )
     }
-}
 
-impl Wallet {
-    pub(super) fn locked(&mut self) -> bool {
+    pub(crate) fn locked(&self) -> bool {
         self.locked
     }
 
-    pub(super) fn deposit(&mut self, amount: f32) {
-        self.available += amount;
-        self.total += amount;
+    pub(crate) fn available(&self) -> f32 {
+        self.available
+    }
+
+    pub(crate) fn held(&self) -> f32 {
+        self.held
+    }
+
+    pub(crate) fn total(&self) -> f32 {
+        self.total
+    }
+
+    /// Fails when the wallet would go over [`Wallet::MAX_AMOUNT`]
+    pub(super) fn deposit(&mut self, amount: f32) -> Result<(), TxRejection> {
+        self.update(self.available + amount, self.held, self.total + amount)
     }
 
-    pub(super) fn withdrawal(&mut self, amount: f32) {
+    pub(super) fn withdrawal(&mut self, amount: f32) -> Result<(), TxRejection> {
         if Self::is_equal_f32(self.available, amount) {
-            self.available = 0.0;
-            self.total = 0.0;
+            // held funds stay in the total
+            self.update(0.0, self.held, self.total - self.available)
         } else if self.available > amount {
-            self.available -= amount;
-            self.total -= amount;
+            self.update(self.available - amount, self.held, self.total - amount)
         } else {
-            log::warn!(
-                "There is no enough funds to withdraw. Available funds: {}, requested: {amount}",
-                self.available
-            );
+            tracing::debug!(available = self.available, amount, "Not enough funds to withdraw");
+            Err(TxRejection::InsufficientFunds)
         }
     }
 
-    pub(super) fn dispute(&mut self, amount: f32) -> bool {
-        //println!("dispute {}, {}", self.available, amount);
-        if Self::is_equal_f32(self.available, amount) {
-            self.available = 0.0;
-            self.held = amount;
-            true
+    /// hold disputed funds. Without `require_available_funds` they are held even if available
+    /// funds go negative
+    pub(super) fn dispute(
+        &mut self,
+        amount: f32,
+        require_available_funds: bool,
+    ) -> Result<(), TxRejection> {
+        if !require_available_funds {
+            self.update(self.available - amount, self.held + amount, self.total)
+        } else if Self::is_equal_f32(self.available, amount) {
+            self.update(0.0, self.held + self.available, self.total)
         } else if self.available > amount {
-            self.available -= amount;
-            self.held += amount;
-            true
+            self.update(self.available - amount, self.held + amount, self.total)
         } else {
-            log::warn!(
-                "There is no enough funds to dispute. Available funds: {}, requested: {amount}",
-                self.available
-            );
-            false
+            tracing::debug!(available = self.available, amount, "Not enough funds to dispute");
+            Err(TxRejection::InsufficientFunds)
         }
     }
 
-    pub(super) fn resolve(&mut self, amount: f32) {
-        self.available += amount;
+    pub(super) fn resolve(&mut self, amount: f32) -> Result<(), TxRejection> {
+        self.update(self.available + amount, self.held - amount, self.total)
+    }
 
-        self.held -= amount;
+    pub(super) fn chargeback(&mut self, amount: f32, lock: bool) -> Result<(), TxRejection> {
+        self.update(self.available, self.held - amount, self.total - amount)?;
+        self.locked |= lock;
+        Ok(())
     }
 
-    pub(super) fn chargeback(&mut self, amount: f32) {
-        self.held -= amount;
-        self.total -= amount;
-        self.locked = true;
+    /// set new amounts, unless any of them would be out of the range the wallet can hold. The
+    /// wallet is left untouched then
+    fn update(&mut self, available: f32, held: f32, total: f32) -> Result<(), TxRejection> {
+        let in_range = |amount: f32| amount.abs() <= Self::MAX_AMOUNT;
+        if !(in_range(available) && in_range(held) && in_range(total)) {
+            tracing::debug!(available, held, total, "Amount out of range");
+            return Err(TxRejection::AmountOutOfRange);
+        }
+        self.available = available;
+        self.held = held;
+        self.total = total;
+        Ok(())
     }
 
     // helper functions
     fn is_equal_f32(first: f32, second: f32) -> bool {
         // we need precision of 4'th place so epsilon is 0.0001 / 2
-        const E: f32 = 0.10005;
+        const E: f32 = 0.00005;
 
         // simplest case: two floats are identical
         if first == second {
@@ -97,18 +118,11 @@ impl Wallet {
         }
 
         // calculation on floats cause not ideal results. We must take this into account
-        if first > second && (first - second) < E {
-            true
-        } else if second > first && (second - first) < E {
-            true
-        } else {
-            false
-        }
+        (first > second && (first - second) < E) || (second > first && (second - first) < E)
     }
 
-    fn round(first: f32) -> Decimal {
-        // this can't be infinite or NaN because we have only addition and subtraction
+    pub(crate) fn round(first: f32, precision: u32) -> Decimal {
         let dec = Decimal::from_f32(first).unwrap_or_default();
-        dec.round_dp(4).into()
+        dec.round_dp(precision)
     }
 }
@@ -1,8 +1,15 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Formatter},
+    time::Instant,
+};
 
-use crate::{EngineError, Record};
+use tokio::sync::oneshot;
+use tracing::Span;
 
-pub(crate) type TxId = u16;
+use crate::{engine::ClientState, EngineError, Record};
+
+pub type TxId = u16;
 pub(super) type Transactions = BTreeMap<TxId, TxResult>;
 
 #[derive(Debug)]
@@ -13,23 +20,147 @@ pub(crate) enum TxAction {
     Resolve,
     Chargeback,
     Close,
+    /// ask client task for a copy of its current state, without closing it
+    Snapshot(oneshot::Sender<ClientState>),
 }
 
-#[derive(Debug)]
+impl TxAction {
+    /// type of the transaction, `None` for control messages
+    pub(crate) fn ty(&self) -> Option<TxType> {
+        match self {
+            Self::Deposit(_) => Some(TxType::Deposit),
+            Self::Withdrawal(_) => Some(TxType::Withdrawal),
+            Self::Dispute => Some(TxType::Dispute),
+            Self::Resolve => Some(TxType::Resolve),
+            Self::Chargeback => Some(TxType::Chargeback),
+            Self::Close | Self::Snapshot(_) => None,
+        }
+    }
+}
+
+/// Type of transaction, as written in the `type` column of the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TxType {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    Resolve,
+    Chargeback,
+}
+
+impl TxType {
+    pub const ALL: [TxType; 5] =
+        [Self::Deposit, Self::Withdrawal, Self::Dispute, Self::Resolve, Self::Chargeback];
+}
+
+impl Display for TxType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Deposit => "deposit",
+            Self::Withdrawal => "withdrawal",
+            Self::Dispute => "dispute",
+            Self::Resolve => "resolve",
+            Self::Chargeback => "chargeback",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub(crate) enum TxResult {
     Deposited(f32),
     Disputed(f32),
 }
 
+/// Reason why a transaction didn't change the client wallet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum TxRejection {
+    AccountLocked,
+    InsufficientFunds,
+    UnknownTx,
+    AlreadyDisputed,
+    NotDisputed,
+    /// the wallet would go over the largest amount it can hold
+    AmountOutOfRange,
+}
+
+impl TxRejection {
+    pub const ALL: [TxRejection; 6] = [
+        Self::AccountLocked,
+        Self::InsufficientFunds,
+        Self::UnknownTx,
+        Self::AlreadyDisputed,
+        Self::NotDisputed,
+        Self::AmountOutOfRange,
+    ];
+
+    /// short machine friendly name of the reason, eg. `insufficient_funds`
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::AccountLocked => "account_locked",
+            Self::InsufficientFunds => "insufficient_funds",
+            Self::UnknownTx => "unknown_tx",
+            Self::AlreadyDisputed => "already_disputed",
+            Self::NotDisputed => "not_disputed",
+            Self::AmountOutOfRange => "amount_out_of_range",
+        }
+    }
+}
+
+impl Display for TxRejection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            Self::AccountLocked => "account is locked",
+            Self::InsufficientFunds => "insufficient available funds",
+            Self::UnknownTx => "unknown transaction",
+            Self::AlreadyDisputed => "transaction is already disputed",
+            Self::NotDisputed => "transaction is not disputed",
+            Self::AmountOutOfRange => "wallet would go over the largest supported amount",
+        };
+        write!(f, "{reason}")
+    }
+}
+
+/// Result of a transaction sent back by the client task, if anybody asked for it
+pub type TxOutcome = Result<(), TxRejection>;
+
 #[derive(Debug)]
 pub(crate) struct TransactionInfo {
     id: TxId,
     tx: TxAction,
+    outcome: Option<oneshot::Sender<TxOutcome>>,
+    created_at: Instant,
+    line: Option<u64>,
+    span: Span,
 }
 
 impl TransactionInfo {
     pub(crate) fn close() -> Self {
-        Self { id: u16::MAX, tx: TxAction::Close }
+        Self {
+            id: u16::MAX,
+            tx: TxAction::Close,
+            outcome: None,
+            created_at: Instant::now(),
+            line: None,
+            span: Span::none(),
+        }
+    }
+
+    pub(crate) fn snapshot() -> (Self, oneshot::Receiver<ClientState>) {
+        let (tx, rx) = oneshot::channel();
+        let tx = TxAction::Snapshot(tx);
+        (
+            Self {
+                id: u16::MAX,
+                tx,
+                outcome: None,
+                created_at: Instant::now(),
+                line: None,
+                span: Span::none(),
+            },
+            rx,
+        )
     }
 
     pub(crate) fn from_record(r: Record) -> Result<Self, EngineError> {
@@ -40,7 +171,7 @@ impl TransactionInfo {
                         "The amount field is missing for deposit transaction in csv".to_string(),
                     ));
                 };
-                TxAction::Deposit(amount)
+                TxAction::Deposit(Self::check_amount(amount)?)
             },
             "withdrawal" => {
                 let Some(amount) = r.amount else {
@@ -50,7 +181,7 @@ impl TransactionInfo {
                             .to_string(),
                     ));
                 };
-                TxAction::Withdrawal(amount)
+                TxAction::Withdrawal(Self::check_amount(amount)?)
             },
             "dispute" => {
                 let None = r.amount else {
@@ -84,13 +215,64 @@ impl TransactionInfo {
             },
         };
 
-        Ok(Self { id: r.tx_id, tx })
+        // the span is created only when debug traces are enabled, it's too costly to create one for
+        // every transaction otherwise
+        let span = tracing::debug_span!(
+            "transaction",
+            client = r.client_id,
+            tx = r.tx_id,
+            line = r.line,
+            action = %r.ty
+        );
+        Ok(Self { id: r.tx_id, tx, outcome: None, created_at: Instant::now(), line: r.line, span })
+    }
+
+    /// NaN, infinite or negative amount would break the wallet for good, eg. NaN is never less
+    /// than available funds
+    fn check_amount(amount: f32) -> Result<f32, EngineError> {
+        if !amount.is_finite() || amount < 0.0 {
+            return Err(EngineError::RecordError(format!(
+                "The amount must be a finite, non-negative number, found {amount}"
+            )));
+        }
+        Ok(amount)
+    }
+
+    /// ask the client task to report the outcome of this transaction
+    pub(crate) fn with_outcome(mut self) -> (Self, oneshot::Receiver<TxOutcome>) {
+        let (tx, rx) = oneshot::channel();
+        self.outcome = Some(tx);
+        (self, rx)
+    }
+
+    pub(crate) fn take_outcome(&mut self) -> Option<oneshot::Sender<TxOutcome>> {
+        self.outcome.take()
     }
 
     pub(crate) fn tx(&self) -> &TxAction {
         &self.tx
     }
 
+    pub(crate) fn into_tx(self) -> TxAction {
+        self.tx
+    }
+
+    /// span of the transaction, so everything logged while it's processed carries its client, tx
+    /// id, input line and action
+    pub(crate) fn span(&self) -> &Span {
+        &self.span
+    }
+
+    /// line of the input with the transaction, if known
+    pub(crate) fn line(&self) -> Option<u64> {
+        self.line
+    }
+
+    /// when the transaction was accepted by the engine
+    pub(crate) fn created_at(&self) -> Instant {
+        self.created_at
+    }
+
     pub(crate) fn id(&self) -> TxId {
         self.id
     }
@@ -8,8 +8,12 @@ use crate::{
 
 #[derive(Error, Debug)]
 pub enum EngineError {
+    #[error("AuditError: {0}")]
+    AuditError(String),
     #[error("ClientNotExists: {0}")]
     ClientNotExists(ClientId),
+    #[error("ConfigError: {0}")]
+    ConfigError(String),
     #[error("CsvError: {0}")]
     CsvError(#[from] csv::Error),
     #[error("Failed to get wallet from client. THIS SHOULD NOT HAPPEN")]
@@ -18,10 +22,16 @@ pub enum EngineError {
     IoError(#[from] std::io::Error),
     #[error("InputFileError: {0}")]
     InputFileError(String),
+    #[error("Mt940Error: {0}")]
+    Mt940Error(String),
+    #[error("OutputSchemaError: {0}")]
+    OutputSchemaError(String),
     #[error("JoinError: {0}")]
     JoinError(#[from] JoinError),
     #[error("RecordError: {0}")]
     RecordError(String),
+    #[error("StateError: {0}")]
+    StateError(String),
     #[error("SendTransactionError: {0}")]
     SendTransactionError(String),
 }
@@ -31,3 +41,14 @@ impl From<SendError<TransactionInfo>> for EngineError {
         SendTransactionError(format!("{:?}", value.0))
     }
 }
+
+impl EngineError {
+    /// the error is caused by a single row of the input, so the rest of it can still be processed
+    pub fn is_invalid_row(&self) -> bool {
+        match self {
+            Self::CsvError(e) => !e.is_io_error(),
+            Self::RecordError(_) => true,
+            _ => false,
+        }
+    }
+}
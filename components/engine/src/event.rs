@@ -0,0 +1,33 @@
+use crate::{
+    engine::{ClientId, ClientState, TxId, TxType},
+    Balance,
+};
+
+/// Emitted by the client task every time a transaction changes the client wallet
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct WalletEvent {
+    /// transaction which changed the wallet
+    pub tx: TxId,
+    /// type of the transaction, eg. deposit or chargeback
+    #[serde(rename = "type")]
+    pub ty: TxType,
+    /// wallet right after the change
+    #[serde(flatten)]
+    pub balance: Balance,
+}
+
+impl WalletEvent {
+    pub(crate) fn new(
+        id: ClientId,
+        tx: TxId,
+        ty: TxType,
+        state: &ClientState,
+        precision: u32,
+    ) -> Self {
+        Self { tx, ty, balance: Balance::new(id, state, precision) }
+    }
+
+    pub fn client(&self) -> ClientId {
+        self.balance.client
+    }
+}
@@ -0,0 +1,83 @@
+//! Entry points of the fuzz targets in `fuzz/`. None of them may panic, whatever the input is.
+//! Besides that they check invariants: parsed amounts are finite and non-negative, and every
+//! wallet is finite, has `total == available + held` and no negative held funds once the input is
+//! processed
+
+use crate::{
+    engine::{
+        transaction::{TransactionInfo, TxAction},
+        ClientId, Wallet,
+    },
+    Engine, InvalidRowsLimit, Record,
+};
+
+/// parse csv into records and each of them into a transaction
+pub fn parse_records(data: &[u8]) {
+    let Ok(records) = Record::csv_records(data) else {
+        return;
+    };
+    for record in records.flatten() {
+        check_transaction(TransactionInfo::from_record(record));
+    }
+}
+
+/// Convert a record made straight from bytes to a transaction: client and tx ids, bits of the
+/// amount and the type. So amounts can be NaN, infinite, negative or subnormal, which csv rarely
+/// gives
+pub fn from_record(data: &[u8]) {
+    if data.len() < 11 {
+        return;
+    }
+    let client_id = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+    let tx_id = u16::from_le_bytes([data[4], data[5]]);
+    let amount = (data[6] & 1 == 1)
+        .then(|| f32::from_bits(u32::from_le_bytes([data[7], data[8], data[9], data[10]])));
+    let ty = String::from_utf8_lossy(&data[11..]).into_owned();
+
+    let record = Record { ty, client_id, tx_id, amount, line: None };
+    check_transaction(TransactionInfo::from_record(record));
+}
+
+/// process csv with the whole engine, skipping invalid rows, and check wallets of all clients
+pub fn process(data: &[u8]) {
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    runtime.block_on(async {
+        let limit = Some(InvalidRowsLimit::default());
+        let Ok(mut engine) = crate::read_transactions(data, Engine::new(), limit).await else {
+            return;
+        };
+
+        let precision = engine.config().precision;
+        for (client, state) in engine.snapshots().await.unwrap() {
+            check_wallet(client, state.wallet(), precision);
+        }
+    });
+}
+
+fn check_transaction(tx_info: Result<TransactionInfo, crate::EngineError>) {
+    if let Ok(tx_info) = tx_info {
+        if let TxAction::Deposit(amount) | TxAction::Withdrawal(amount) = tx_info.tx() {
+            assert!(amount.is_finite() && *amount >= 0.0, "accepted amount {amount}");
+        }
+    }
+}
+
+fn check_wallet(client: ClientId, wallet: &Wallet, precision: u32) {
+    let (available, held, total) =
+        (f64::from(wallet.available()), f64::from(wallet.held()), f64::from(wallet.total()));
+    assert!(
+        available.is_finite() && held.is_finite() && total.is_finite(),
+        "client {client}: not finite {wallet:?}"
+    );
+
+    // half of the last printed digit. Amounts too large for f32 to keep that precision are off
+    // by their own rounding error instead
+    let largest = available.abs().max(held.abs()).max(total.abs());
+    let tolerance =
+        (0.5 * 10f64.powi(-(precision as i32))).max(4.0 * f64::from(f32::EPSILON) * largest);
+    assert!(held >= -tolerance, "client {client}: negative held funds in {wallet:?}");
+    assert!(
+        total == available + held || (total - (available + held)).abs() <= tolerance,
+        "client {client}: total isn't available + held in {wallet:?}"
+    );
+}
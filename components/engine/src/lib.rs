@@ -1,41 +1,291 @@
 extern crate core;
 
-use csv::Trim;
-
-use crate::{engine::Engine, error::EngineError, record::Record};
+pub use crate::{
+    audit::{AuditEntry, WalletValues},
+    camt053::Camt053Options,
+    config::{DisputePolicy, EngineConfig, LockPolicy},
+    engine::{ClientId, Engine, TxId, TxOutcome, TxRejection, TxType},
+    error::EngineError,
+    event::WalletEvent,
+    metrics::{ClientVolume, Metrics},
+    output::{Balance, Column, OutputOrder, OutputSchema},
+    record::Record,
+    simulation::{BalanceChange, SimulatedRejection, Simulation},
+    stats::RunStats,
+    validate::{ValidationIssue, ValidationReport},
+};
 
+mod audit;
+mod camt053;
+mod config;
 mod engine;
 mod error;
+mod event;
+/// entry points of the fuzz targets
+#[cfg(any(test, feature = "fuzzing"))]
+pub mod fuzz;
+mod metrics;
+mod mt940;
+mod output;
 mod record;
+mod simulation;
+mod stats;
+mod validate;
+
+/// Knobs which change how transactions are processed and how the result is written out
+#[derive(Debug, Default, Clone)]
+pub struct ProcessOptions {
+    pub order: OutputOrder,
+    pub schema: OutputSchema,
+    /// file every change of a wallet is appended to, see [`Engine::set_audit_log`]
+    pub audit_log: Option<std::path::PathBuf>,
+    /// skip rows which aren't correct transactions, up to the limit, instead of stopping on the
+    /// first one. Skipped rows are logged and counted as invalid in [`RunStats`]
+    pub skip_invalid: Option<InvalidRowsLimit>,
+    /// count metrics of the run in this handle, eg. to show its progress while it runs
+    pub metrics: Option<std::sync::Arc<Metrics>>,
+    /// policies of the engine
+    pub config: EngineConfig,
+}
+
+/// Limit of invalid rows skipped with [`ProcessOptions::skip_invalid`]. The run is aborted when
+/// either of them is exceeded
+#[derive(Debug, Default, Clone, Copy)]
+pub struct InvalidRowsLimit {
+    pub max_count: Option<u64>,
+    /// share of invalid rows in all rows read, between 0 and 1
+    pub max_ratio: Option<f64>,
+}
+
+impl InvalidRowsLimit {
+    /// rows read before the ratio is checked, so an invalid row at the beginning of the input
+    /// doesn't abort the run. The ratio of the whole input is always checked at its end
+    const MIN_ROWS_FOR_RATIO: u64 = 100;
+
+    /// fails if `invalid` rows of `rows` read so far are over the limit. `finished` is set once
+    /// the whole input was read
+    pub fn check(&self, invalid: u64, rows: u64, finished: bool) -> Result<(), EngineError> {
+        if let Some(max_count) = self.max_count.filter(|max_count| invalid > *max_count) {
+            return Err(EngineError::InputFileError(format!(
+                "too many invalid rows: {invalid} of {rows} read (max {max_count})"
+            )));
+        }
+
+        let checked = finished || rows >= Self::MIN_ROWS_FOR_RATIO;
+        if let Some(max_ratio) = self.max_ratio.filter(|_| checked && rows > 0) {
+            if invalid as f64 / rows as f64 > max_ratio {
+                return Err(EngineError::InputFileError(format!(
+                    "too many invalid rows: {invalid} of {rows} read (max ratio {max_ratio})"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
 
 #[tokio::main]
 pub async fn process_transactions<R: std::io::Read, W: std::io::Write>(
     io_reader: R,
     io_writer: W,
 ) -> Result<(), EngineError> {
-    internal_process_transactions(io_reader, io_writer).await
+    internal_process_transactions(io_reader, io_writer, &ProcessOptions::default()).await?;
+    Ok(())
+}
+
+/// Process transactions like [`process_transactions`] with the given options. Returns summary of
+/// the run
+#[tokio::main]
+pub async fn process_transactions_with_options<R: std::io::Read, W: std::io::Write>(
+    io_reader: R,
+    io_writer: W,
+    options: &ProcessOptions,
+) -> Result<RunStats, EngineError> {
+    internal_process_transactions(io_reader, io_writer, options).await
+}
+
+/// Check the whole input without processing it and report every problem found, with its line.
+/// Fails only if the input can't be read
+pub fn validate_transactions<R: std::io::Read>(
+    io_reader: R,
+) -> Result<ValidationReport, EngineError> {
+    validate::validate(io_reader)
+}
+
+/// Process transactions like [`process_transactions`], but instead of final balances print
+/// statements of the given clients: every transaction in order with the wallet after it
+#[tokio::main]
+pub async fn export_statements<R: std::io::Read, W: std::io::Write>(
+    io_reader: R,
+    io_writer: W,
+    client_ids: &[ClientId],
+    config: &EngineConfig,
+) -> Result<(), EngineError> {
+    internal_export_statements(io_reader, io_writer, client_ids, config).await
+}
+
+/// Process transactions like [`process_transactions`], but instead of final balances print audit
+/// trails of the given clients: every change of their wallets, one json [`AuditEntry`] per line
+#[tokio::main]
+pub async fn export_audit_trails<R: std::io::Read, W: std::io::Write>(
+    io_reader: R,
+    io_writer: W,
+    client_ids: &[ClientId],
+    config: &EngineConfig,
+) -> Result<(), EngineError> {
+    internal_export_audit_trails(io_reader, io_writer, client_ids, config).await
+}
+
+/// Process transactions and export final balances and booked transactions of the given clients
+/// as ISO 20022 camt.053 document
+#[tokio::main]
+pub async fn export_camt053<R: std::io::Read, W: std::io::Write>(
+    io_reader: R,
+    io_writer: W,
+    client_ids: &[ClientId],
+    options: &Camt053Options,
+    config: &EngineConfig,
+) -> Result<(), EngineError> {
+    internal_export_camt053(io_reader, io_writer, client_ids, options, config).await
+}
+
+/// Process deposits and withdrawals from SWIFT MT940 statements instead of csv. Accounts of the
+/// statements are mapped to clients with `accounts_reader`, a csv file with `account,client` header
+#[tokio::main]
+pub async fn process_mt940_transactions<R: std::io::Read, A: std::io::Read, W: std::io::Write>(
+    io_reader: R,
+    accounts_reader: A,
+    io_writer: W,
+    options: &ProcessOptions,
+) -> Result<RunStats, EngineError> {
+    internal_process_mt940_transactions(io_reader, accounts_reader, io_writer, options).await
 }
 
 async fn internal_process_transactions<R: std::io::Read, W: std::io::Write>(
     io_reader: R,
     io_writer: W,
+    options: &ProcessOptions,
+) -> Result<RunStats, EngineError> {
+    let engine = new_engine(options)?;
+    let mut engine = read_transactions(io_reader, engine, options.skip_invalid).await?;
+
+    // Once each transaction is processed, we can receive portfolios (fund summaries) for each client
+    // and print them in the requested order
+    engine.print_wallets(io_writer, options.order, &options.schema).await?;
+    Ok(engine.stats())
+}
+
+async fn internal_export_statements<R: std::io::Read, W: std::io::Write>(
+    io_reader: R,
+    io_writer: W,
+    client_ids: &[ClientId],
+    config: &EngineConfig,
 ) -> Result<(), EngineError> {
-    let mut rdr =
-        csv::ReaderBuilder::new().trim(Trim::All).comment(Some(b'#')).from_reader(io_reader);
+    let engine = Engine::with_config(config.clone())?.with_statements();
+    let mut engine = read_transactions(io_reader, engine, None).await?;
+    engine.print_statements(io_writer, client_ids).await?;
+    Ok(())
+}
+
+async fn internal_export_audit_trails<R: std::io::Read, W: std::io::Write>(
+    io_reader: R,
+    mut io_writer: W,
+    client_ids: &[ClientId],
+    config: &EngineConfig,
+) -> Result<(), EngineError> {
+    // the whole trail of the requested clients is printed
+    let engine = Engine::with_config(config.clone())?.with_audit_trails(usize::MAX);
+    let mut engine = read_transactions(io_reader, engine, None).await?;
+    for (_, state) in engine.client_states(client_ids).await? {
+        for entry in state.audit() {
+            serde_json::to_writer(&mut io_writer, entry)
+                .map_err(|e| EngineError::AuditError(e.to_string()))?;
+            writeln!(io_writer)?;
+        }
+    }
+    Ok(())
+}
 
-    let mut engine = Engine::new();
+async fn internal_export_camt053<R: std::io::Read, W: std::io::Write>(
+    io_reader: R,
+    io_writer: W,
+    client_ids: &[ClientId],
+    options: &Camt053Options,
+    config: &EngineConfig,
+) -> Result<(), EngineError> {
+    let engine = Engine::with_config(config.clone())?.with_statements();
+    let mut engine = read_transactions(io_reader, engine, None).await?;
+    let states = engine.client_states(client_ids).await?;
+    camt053::write_document(io_writer, &states, options, config.precision)?;
+    Ok(())
+}
+
+async fn internal_process_mt940_transactions<
+    R: std::io::Read,
+    A: std::io::Read,
+    W: std::io::Write,
+>(
+    io_reader: R,
+    accounts_reader: A,
+    io_writer: W,
+    options: &ProcessOptions,
+) -> Result<RunStats, EngineError> {
+    let accounts = mt940::read_accounts(accounts_reader)?;
+
+    let mut engine = new_engine(options)?;
+    for record in mt940::parse_records(io_reader, &accounts)? {
+        engine.process_record(record).await?;
+    }
+
+    engine.print_wallets(io_writer, options.order, &options.schema).await?;
+    Ok(engine.stats())
+}
+
+/// engine which writes the audit log and counts metrics as requested in options
+fn new_engine(options: &ProcessOptions) -> Result<Engine, EngineError> {
+    let engine = Engine::with_config(options.config.clone())?
+        .with_metrics(options.metrics.clone().unwrap_or_default());
+    if let Some(path) = &options.audit_log {
+        // the log is append-only, so previous runs are never overwritten
+        engine.set_audit_log(std::fs::OpenOptions::new().create(true).append(true).open(path)?);
+    }
+    Ok(engine)
+}
+
+async fn read_transactions<R: std::io::Read>(
+    io_reader: R,
+    mut engine: Engine,
+    skip_invalid: Option<InvalidRowsLimit>,
+) -> Result<Engine, EngineError> {
+    let (mut rows, mut invalid) = (0, 0);
 
     // we need to process records (transactions) in the right order, so we wait until
     // the transaction is sent and then move on to the next one
-    for result in rdr.deserialize() {
-        let record: Record = result?;
-        engine.process_record(record).await?;
+    for record in Record::csv_records(io_reader)? {
+        rows += 1;
+        let result = match record {
+            Ok(record) => engine.process_record(record).await,
+            Err(e) => {
+                if e.is_invalid_row() {
+                    tracing::warn!(error = %e, "Invalid row");
+                    engine.invalid_row();
+                }
+                Err(e)
+            },
+        };
+
+        match (result, skip_invalid) {
+            (Err(e), Some(limit)) if e.is_invalid_row() => {
+                invalid += 1;
+                limit.check(invalid, rows, false)?;
+            },
+            (result, _) => result?,
+        }
     }
 
-    // Once each transaction is processed, we can receive portfolios (fund summaries) for each client
-    // we don't need them sorted, so we just print them out of order
-    engine.print_wallets(io_writer).await?;
-    Ok(())
+    if let Some(limit) = skip_invalid {
+        limit.check(invalid, rows, true)?;
+    }
+    Ok(engine)
 }
 
 #[cfg(test)]
@@ -43,26 +293,23 @@ mod tests {
     use std::io::Cursor;
 
     use super::*;
-    use crate::EngineError::{CsvError, RecordError};
+    use crate::EngineError::{ConfigError, CsvError, InputFileError, RecordError};
 
     async fn test_process_transaction<R: std::io::Read>(
         io_reader: R,
     ) -> Result<String, EngineError> {
-        let mut output_str = Cursor::new(Vec::<u8>::new());
-        internal_process_transactions(io_reader, &mut output_str).await?;
-
-        Ok(String::from_utf8(output_str.into_inner()).unwrap())
+        test_process_transaction_ordered(io_reader, OutputOrder::ClientId).await
     }
 
-    #[tokio::test]
-    async fn input_file_works() {
-        let expected_str = r#"client,available,held,total
-1,1.5,0,1.5,false
-2,2,0,2,false"#;
-        let file = std::fs::File::open("..\\..\\transaction.csv").unwrap();
+    async fn test_process_transaction_ordered<R: std::io::Read>(
+        io_reader: R,
+        order: OutputOrder,
+    ) -> Result<String, EngineError> {
+        let mut output_str = Cursor::new(Vec::<u8>::new());
+        let options = ProcessOptions { order, ..Default::default() };
+        internal_process_transactions(io_reader, &mut output_str, &options).await?;
 
-        let output_str = test_process_transaction(file).await.unwrap();
-        assert_eq!(output_str.as_str(), expected_str);
+        Ok(String::from_utf8(output_str.into_inner()).unwrap())
     }
 
     #[tokio::test]
@@ -117,6 +364,69 @@ dispute, 1,1,1"#;
         assert_eq!(error.to_string(), "The amount should be empty for dispute in csv");
     }
 
+    #[tokio::test]
+    async fn incorrect_amounts() {
+        for (amount, found) in [("NaN", "NaN"), ("inf", "inf"), ("-1.5", "-1.5")] {
+            let input_str = format!("type,client,tx,amount\nwithdrawal,1,1,{amount}");
+
+            let Err(RecordError(error)) = test_process_transaction(input_str.as_bytes()).await
+            else {
+                panic!();
+            };
+            let expected =
+                format!("The amount must be a finite, non-negative number, found {found}");
+            assert_eq!(error, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn amounts_out_of_wallet_range_are_rejected() {
+        let mut engine = Engine::new();
+        let input_str = r#"type,client,tx,amount
+deposit,1,1,5e28
+deposit,1,2,5e28
+withdrawal,1,3,1e28
+deposit,2,4,3e38
+deposit,1,5,3e28
+deposit,1,6,1e28"#;
+        let records = Record::from_csv(input_str.as_bytes()).unwrap();
+        let mut outcomes = Vec::new();
+        for outcome in engine.submit_records(records).await.unwrap() {
+            outcomes.push(outcome.await.unwrap());
+        }
+        let out_of_range = Err(TxRejection::AmountOutOfRange);
+        assert_eq!(outcomes, [Ok(()), out_of_range, Ok(()), out_of_range, Ok(()), out_of_range]);
+
+        // rejected deposits didn't change the wallets
+        let totals = engine
+            .balances(OutputOrder::ClientId)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|balance| balance.total)
+            .collect::<Vec<_>>();
+        let expected = rust_decimal::Decimal::try_from(5e28_f32 - 1e28 + 3e28).unwrap();
+        assert_eq!(totals, [expected, 0.into()]);
+    }
+
+    #[test]
+    fn fuzz_entry_points_keep_invariants() {
+        let inputs = [
+            "type,client,tx,amount\ndeposit,1,1,NaN\ndispute,1,1,\nwithdrawal,1,2,-inf",
+            "type,client,tx,amount\ndeposit,1,1,3e38\ndeposit,1,2,3e38\ndispute,1,2,",
+            "type,client,tx,amount\ndeposit,1,1,0.1\ndeposit,1,2,0.2\ndispute,1,1,\nwithdrawal,1,\
+             3,0.2",
+            "\u{0}garbage\n,,,\n\"",
+        ];
+        for input in inputs {
+            fuzz::parse_records(input.as_bytes());
+            fuzz::process(input.as_bytes());
+        }
+
+        let nan_deposit = [[1, 0, 0, 0, 1, 0, 1].as_slice(), &f32::NAN.to_le_bytes(), b"deposit"];
+        fuzz::from_record(&nan_deposit.concat());
+    }
+
     #[tokio::test]
     async fn incorrect_csv_format_5() {
         let input_str = r#"type, client, tx, amount
@@ -138,9 +448,10 @@ withdrawal, 1, 4, 1.5
 dispute, 1,1,
 withdrawal, 2, 5, 3.0"#;
 
-        let expected_str = r#"client,available,held,total
+        let expected_str = r#"client,available,held,total,locked
 1,0.5,1.1111,1.6111,false
-2,2,0,2,false"#;
+2,2,0,2,false
+"#;
 
         let output_str = test_process_transaction(input_str.as_bytes()).await.unwrap();
         assert_eq!(output_str.as_str(), expected_str);
@@ -157,9 +468,10 @@ dispute, 1,1,
 resolve, 1,1,
 withdrawal, 2, 5, 3.0"#;
 
-        let expected_str = r#"client,available,held,total
+        let expected_str = r#"client,available,held,total,locked
 1,1.5,0,1.5,false
-2,2,0,2,false"#;
+2,2,0,2,false
+"#;
 
         let output_str = test_process_transaction(input_str.as_bytes()).await.unwrap();
         assert_eq!(output_str.as_str(), expected_str);
@@ -176,9 +488,10 @@ dispute, 1,1,
 chargeback, 1,1,
 withdrawal, 2, 5, 3.0"#;
 
-        let expected_str = r#"client,available,held,total
+        let expected_str = r#"client,available,held,total,locked
 1,0.5,0,0.5,true
-2,2,0,2,false"#;
+2,2,0,2,false
+"#;
 
         let output_str = test_process_transaction(input_str.as_bytes()).await.unwrap();
         assert_eq!(output_str.as_str(), expected_str);
@@ -196,9 +509,10 @@ dispute, 1,1,
 chargeback, 1,1,
 withdrawal, 2, 5, 3.0"#;
 
-        let expected_str = r#"client,available,held,total
+        let expected_str = r#"client,available,held,total,locked
 1,0,0,0,false
-2,2,0,2,false"#;
+2,2,0,2,false
+"#;
 
         let output_str = test_process_transaction(input_str.as_bytes()).await.unwrap();
         assert_eq!(output_str.as_str(), expected_str);
@@ -209,8 +523,9 @@ withdrawal, 2, 5, 3.0"#;
         let input_str = r#"type, client, tx, amount
 withdrawal, 2, 5, 3.0"#;
 
-        let expected_str = r#"client,available,held,total
-2,0,0,0,false"#;
+        let expected_str = r#"client,available,held,total,locked
+2,0,0,0,false
+"#;
 
         let output_str = test_process_transaction(input_str.as_bytes()).await.unwrap();
         assert_eq!(output_str.as_str(), expected_str);
@@ -221,8 +536,9 @@ withdrawal, 2, 5, 3.0"#;
         let input_str = r#"type, client, tx, amount
 dispute, 2, 52,"#;
 
-        let expected_str = r#"client,available,held,total
-2,0,0,0,false"#;
+        let expected_str = r#"client,available,held,total,locked
+2,0,0,0,false
+"#;
 
         let output_str = test_process_transaction(input_str.as_bytes()).await.unwrap();
         assert_eq!(output_str.as_str(), expected_str);
@@ -240,9 +556,10 @@ chargeback, 1,1,
 withdrawal, 2, 5, 3.0
 deposit, 1, 1, 1.0"#;
 
-        let expected_str = r#"client,available,held,total
+        let expected_str = r#"client,available,held,total,locked
 1,0.5,0,0.5,true
-2,2,0,2,false"#;
+2,2,0,2,false
+"#;
 
         let output_str = test_process_transaction(input_str.as_bytes()).await.unwrap();
         assert_eq!(output_str.as_str(), expected_str);
@@ -257,8 +574,9 @@ withdrawal, 1, 2, 1.0
 dispute, 1,1,
 chargeback, 1,1,"#;
 
-        let expected_str = r#"client,available,held,total
-1,0,0,0,false"#;
+        let expected_str = r#"client,available,held,total,locked
+1,0,0,0,false
+"#;
 
         let output_str = test_process_transaction(input_str.as_bytes()).await.unwrap();
         assert_eq!(output_str.as_str(), expected_str);
@@ -272,8 +590,9 @@ deposit, 1, 2, 3.0
 dispute, 1, 2,
 withdrawal, 1,3, 2.1111"#;
 
-        let expected_str = r#"client,available,held,total
-1,1,3,4,false"#;
+        let expected_str = r#"client,available,held,total,locked
+1,1,3,4,false
+"#;
 
         let output_str = test_process_transaction(input_str.as_bytes()).await.unwrap();
         assert_eq!(output_str.as_str(), expected_str);
@@ -288,8 +607,9 @@ dispute, 1, 2,
 withdrawal, 1,3, 2.1111
 resolve, 1,2,"#;
 
-        let expected_str = r#"client,available,held,total
-1,4,0,4,false"#;
+        let expected_str = r#"client,available,held,total,locked
+1,4,0,4,false
+"#;
 
         let output_str = test_process_transaction(input_str.as_bytes()).await.unwrap();
         assert_eq!(output_str.as_str(), expected_str);
@@ -307,8 +627,9 @@ withdrawal, 1,3, 2.1111
 dispute, 1, 2,
 chargeback, 1,2,"#;
 
-        let expected_str = r#"client,available,held,total
-1,1.8889,0,1.8889,false"#;
+        let expected_str = r#"client,available,held,total,locked
+1,1.8889,0,1.8889,false
+"#;
 
         let output_str = test_process_transaction(input_str.as_bytes()).await.unwrap();
         assert_eq!(output_str.as_str(), expected_str);
@@ -323,9 +644,10 @@ deposit, 6, 3, 5.1234
 dispute, 6, 3,
 resolve, 6, 3,"#;
 
-        let expected_str = r#"client,available,held,total
+        let expected_str = r#"client,available,held,total,locked
 3,1.0234,0,1.0234,false
-6,5.1234,0,5.1234,false"#;
+6,5.1234,0,5.1234,false
+"#;
 
         let output_str = test_process_transaction(input_str.as_bytes()).await.unwrap();
         assert_eq!(output_str.as_str(), expected_str);
@@ -343,11 +665,739 @@ resolve, 6, 3,
 dispute, 6, 3,
 chargeback, 6, 3,"#;
 
-        let expected_str = r#"client,available,held,total
+        let expected_str = r#"client,available,held,total,locked
 3,0,0,0,false
-6,0,0,0,true"#;
+6,0,0,0,true
+"#;
+
+        let output_str = test_process_transaction(input_str.as_bytes()).await.unwrap();
+        assert_eq!(output_str.as_str(), expected_str);
+    }
+
+    #[tokio::test]
+    async fn sort_by_numeric_client_id() {
+        let input_str = r#"type, client, tx, amount
+deposit, 10, 1, 1.0
+deposit, 2, 2, 2.0
+deposit, 1, 3, 3.0"#;
+
+        let expected_str = r#"client,available,held,total,locked
+1,3,0,3,false
+2,2,0,2,false
+10,1,0,1,false
+"#;
 
         let output_str = test_process_transaction(input_str.as_bytes()).await.unwrap();
         assert_eq!(output_str.as_str(), expected_str);
     }
+
+    #[tokio::test]
+    async fn sort_by_balance() {
+        let input_str = r#"type, client, tx, amount
+deposit, 10, 1, 1.0
+deposit, 2, 2, 2.0
+deposit, 1, 3, 1.0
+deposit, 3, 4, 5.0"#;
+
+        let expected_str = r#"client,available,held,total,locked
+3,5,0,5,false
+2,2,0,2,false
+1,1,0,1,false
+10,1,0,1,false
+"#;
+
+        let output_str =
+            test_process_transaction_ordered(input_str.as_bytes(), OutputOrder::Balance)
+                .await
+                .unwrap();
+        assert_eq!(output_str.as_str(), expected_str);
+    }
+
+    #[tokio::test]
+    async fn extra_output_columns() {
+        let input_str = r#"type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, 1, 2, 3.0
+dispute, 1, 2,
+withdrawal, 1, 3, 5.0
+deposit, 2, 4, 2.0"#;
+
+        let expected_str = r#"client,available,held,total,locked,last_tx,open_disputes,currency
+1,1,3,4,false,2,1,EUR
+2,2,0,2,false,4,0,EUR
+"#;
+
+        let schema = OutputSchema::new(1)
+            .unwrap()
+            .with_column(Column::LastTx)
+            .with_column(Column::OpenDisputes)
+            .with_column(Column::Currency)
+            .with_currency("EUR");
+        let options = ProcessOptions { order: OutputOrder::ClientId, schema, ..Default::default() };
+
+        let mut output_str = Cursor::new(Vec::<u8>::new());
+        internal_process_transactions(input_str.as_bytes(), &mut output_str, &options)
+            .await
+            .unwrap();
+        assert_eq!(String::from_utf8(output_str.into_inner()).unwrap(), expected_str);
+    }
+
+    #[tokio::test]
+    async fn currency_with_separator_is_quoted() {
+        let input_str = "type,client,tx,amount\ndeposit,1,1,1.0";
+        let expected_str =
+            "client,available,held,total,locked,currency\n1,1,0,1,false,\"U,\"\"S\"\n";
+
+        let schema = OutputSchema::default().with_column(Column::Currency).with_currency("U,\"S");
+        let options = ProcessOptions { schema, ..Default::default() };
+
+        let mut output_str = Cursor::new(Vec::<u8>::new());
+        internal_process_transactions(input_str.as_bytes(), &mut output_str, &options)
+            .await
+            .unwrap();
+        assert_eq!(String::from_utf8(output_str.into_inner()).unwrap(), expected_str);
+    }
+
+    #[test]
+    fn unsupported_output_schema_version() {
+        let Err(EngineError::OutputSchemaError(error)) = OutputSchema::new(2) else {
+            panic!();
+        };
+        assert_eq!(error, "Unsupported output schema version: 2. Latest version is 1");
+    }
+
+    #[tokio::test]
+    async fn client_statement() {
+        let input_str = r#"type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, 2, 2, 2.0
+deposit, 1, 3, 2.0
+withdrawal, 1, 4, 5.0
+dispute, 1, 1,
+dispute, 1, 7,
+chargeback, 1, 1,
+deposit, 1, 5, 1.0"#;
+
+        let expected_str = r#"client,tx,type,amount,available,held,total,locked,status,reason
+1,1,deposit,1,1,0,1,false,applied,
+1,3,deposit,2,3,0,3,false,applied,
+1,4,withdrawal,5,3,0,3,false,rejected,insufficient available funds
+1,1,dispute,1,2,1,3,false,applied,
+1,7,dispute,,2,1,3,false,rejected,unknown transaction
+1,1,chargeback,1,2,0,2,true,applied,
+1,5,deposit,1,2,0,2,true,rejected,account is locked
+"#;
+
+        let config = EngineConfig::default();
+        let mut output_str = Cursor::new(Vec::<u8>::new());
+        internal_export_statements(input_str.as_bytes(), &mut output_str, &[1], &config)
+            .await
+            .unwrap();
+        assert_eq!(String::from_utf8(output_str.into_inner()).unwrap(), expected_str);
+    }
+
+    #[tokio::test]
+    async fn statement_of_unknown_client() {
+        let input_str = r#"type, client, tx, amount
+deposit, 1, 1, 1.0"#;
+
+        let config = EngineConfig::default();
+        let mut output_str = Cursor::new(Vec::<u8>::new());
+        let result =
+            internal_export_statements(input_str.as_bytes(), &mut output_str, &[2], &config).await;
+        assert!(matches!(result, Err(EngineError::ClientNotExists(2))));
+    }
+
+    #[tokio::test]
+    async fn camt053_statement() {
+        let input_str = r#"type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, 1, 2, 3.0
+withdrawal, 1, 3, 0.5
+withdrawal, 1, 4, 9.0
+dispute, 1, 2,
+chargeback, 1, 2,"#;
+
+        let expected_str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.02">
+  <BkToCstmrStmt>
+    <GrpHdr>
+      <MsgId>MSG1</MsgId>
+      <CreDtTm>2024-04-30T12:00:00Z</CreDtTm>
+    </GrpHdr>
+    <Stmt>
+      <Id>MSG1-1</Id>
+      <CreDtTm>2024-04-30T12:00:00Z</CreDtTm>
+      <Acct>
+        <Id><Othr><Id>1</Id></Othr></Id>
+        <Ccy>EUR</Ccy>
+      </Acct>
+      <Bal>
+        <Tp><CdOrPrtry><Cd>OPBD</Cd></CdOrPrtry></Tp>
+        <Amt Ccy="EUR">0</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Dt><DtTm>2024-04-30T12:00:00Z</DtTm></Dt>
+      </Bal>
+      <Bal>
+        <Tp><CdOrPrtry><Cd>CLBD</Cd></CdOrPrtry></Tp>
+        <Amt Ccy="EUR">0.5</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Dt><DtTm>2024-04-30T12:00:00Z</DtTm></Dt>
+      </Bal>
+      <Bal>
+        <Tp><CdOrPrtry><Cd>CLAV</Cd></CdOrPrtry></Tp>
+        <Amt Ccy="EUR">0.5</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Dt><DtTm>2024-04-30T12:00:00Z</DtTm></Dt>
+      </Bal>
+      <Ntry>
+        <NtryRef>1</NtryRef>
+        <Amt Ccy="EUR">1</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Sts>BOOK</Sts>
+        <BkTxCd><Prtry><Cd>deposit</Cd></Prtry></BkTxCd>
+        <NtryDtls><TxDtls><Refs>
+          <TxId>1</TxId>
+        </Refs></TxDtls></NtryDtls>
+      </Ntry>
+      <Ntry>
+        <NtryRef>2</NtryRef>
+        <Amt Ccy="EUR">3</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Sts>BOOK</Sts>
+        <BkTxCd><Prtry><Cd>deposit</Cd></Prtry></BkTxCd>
+        <NtryDtls><TxDtls><Refs>
+          <TxId>2</TxId>
+        </Refs></TxDtls></NtryDtls>
+      </Ntry>
+      <Ntry>
+        <NtryRef>3</NtryRef>
+        <Amt Ccy="EUR">0.5</Amt>
+        <CdtDbtInd>DBIT</CdtDbtInd>
+        <Sts>BOOK</Sts>
+        <BkTxCd><Prtry><Cd>withdrawal</Cd></Prtry></BkTxCd>
+        <NtryDtls><TxDtls><Refs>
+          <TxId>3</TxId>
+        </Refs></TxDtls></NtryDtls>
+      </Ntry>
+      <Ntry>
+        <NtryRef>2</NtryRef>
+        <Amt Ccy="EUR">3</Amt>
+        <CdtDbtInd>DBIT</CdtDbtInd>
+        <Sts>BOOK</Sts>
+        <BkTxCd><Prtry><Cd>chargeback</Cd></Prtry></BkTxCd>
+        <NtryDtls><TxDtls><Refs>
+          <TxId>2</TxId>
+        </Refs></TxDtls></NtryDtls>
+      </Ntry>
+    </Stmt>
+  </BkToCstmrStmt>
+</Document>
+"#;
+
+        let options = Camt053Options {
+            message_id: "MSG1".to_string(),
+            created_at: "2024-04-30T12:00:00Z".to_string(),
+            currency: "EUR".to_string(),
+        };
+
+        let config = EngineConfig::default();
+        let mut output_str = Cursor::new(Vec::<u8>::new());
+        internal_export_camt053(input_str.as_bytes(), &mut output_str, &[1], &options, &config)
+            .await
+            .unwrap();
+        assert_eq!(String::from_utf8(output_str.into_inner()).unwrap(), expected_str);
+    }
+
+    #[tokio::test]
+    async fn mt940_statement_lines() {
+        let input_str = r#":20:STMT001
+:25:PL61109010140000071219812874
+:28C:1/1
+:60F:C240430EUR0,00
+:61:2404300430C12,5NTRF1//BANKREF1
+Incoming transfer
+:61:240430D2,25NTRF2
+:61:240430RDE1,NTRF3//BANKREF3
+:62F:C240430EUR11,25
+-
+:20:STMT002
+:25:DE89370400440532013000
+:61:240430C3,NCHK4
+:61:240430RC3,NCHK5
+-"#;
+
+        let accounts_str = r#"account,client
+PL61109010140000071219812874,1
+DE89370400440532013000,2"#;
+
+        let expected_str = r#"client,available,held,total,locked
+1,11.25,0,11.25,false
+2,0,0,0,false
+"#;
+
+        let options = ProcessOptions { order: OutputOrder::ClientId, ..Default::default() };
+        let mut output_str = Cursor::new(Vec::<u8>::new());
+        internal_process_mt940_transactions(
+            input_str.as_bytes(),
+            accounts_str.as_bytes(),
+            &mut output_str,
+            &options,
+        )
+        .await
+        .unwrap();
+        assert_eq!(String::from_utf8(output_str.into_inner()).unwrap(), expected_str);
+    }
+
+    #[tokio::test]
+    async fn mt940_unknown_account() {
+        let input_str = r#":20:STMT001
+:25:PL61109010140000071219812874
+:61:2404300430C12,5NTRF1"#;
+
+        let mut output_str = Cursor::new(Vec::<u8>::new());
+        let Err(EngineError::Mt940Error(error)) = internal_process_mt940_transactions(
+            input_str.as_bytes(),
+            "account,client".as_bytes(),
+            &mut output_str,
+            &ProcessOptions::default(),
+        )
+        .await
+        else {
+            panic!();
+        };
+        assert_eq!(error, "line 2: Unknown account: \"PL61109010140000071219812874\"");
+    }
+
+    #[tokio::test]
+    async fn save_and_load_state() {
+        let input_str = r#"type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, 1, 2, 3.0
+deposit, 2, 3, 2.0"#;
+
+        let mut engine = Engine::new();
+        for record in Record::from_csv(input_str.as_bytes()).unwrap() {
+            engine.process_record(record).await.unwrap();
+        }
+        let mut saved = Vec::new();
+        engine.save_state(&mut saved).await.unwrap();
+        // statements are recorded only for exports
+        assert!(!String::from_utf8_lossy(&saved).contains("statement"));
+
+        // dispute of the deposit made before saving works, so history is restored as well
+        let mut engine = Engine::load_state(saved.as_slice()).unwrap();
+        engine.process_record(Record::from_csv_line("dispute, 1, 2,").unwrap()).await.unwrap();
+
+        let expected_str = r#"client,available,held,total,locked
+1,1,3,4,false
+2,2,0,2,false
+"#;
+
+        let mut output_str = Cursor::new(Vec::<u8>::new());
+        engine
+            .print_wallets(&mut output_str, OutputOrder::ClientId, &OutputSchema::default())
+            .await
+            .unwrap();
+        assert_eq!(String::from_utf8(output_str.into_inner()).unwrap(), expected_str);
+    }
+
+    #[tokio::test]
+    async fn simulate_batch_without_changing_state() {
+        let input_str = r#"type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, 2, 2, 2.0"#;
+        let batch_str = r#"type, client, tx, amount
+dispute, 1, 1,
+withdrawal, 2, 3, 5.0
+deposit, 3, 4, 1.5"#;
+
+        let mut engine = Engine::new();
+        for record in Record::from_csv(input_str.as_bytes()).unwrap() {
+            engine.process_record(record).await.unwrap();
+        }
+        let before = engine.balances(OutputOrder::ClientId).await.unwrap();
+
+        let batch = Record::from_csv(batch_str.as_bytes()).unwrap();
+        let simulation = engine.simulate(batch).await.unwrap();
+        assert_eq!(simulation.transactions, 3);
+        assert_eq!(
+            simulation.rejections,
+            vec![SimulatedRejection {
+                client: 2,
+                tx: 3,
+                line: Some(3),
+                ty: TxType::Withdrawal,
+                rejection: TxRejection::InsufficientFunds,
+            }]
+        );
+        let changes = simulation
+            .changes
+            .iter()
+            .map(|change| (change.client, change.before.is_some(), change.after.held))
+            .collect::<Vec<_>>();
+        assert_eq!(changes, vec![(1, true, 1.into()), (3, false, 0.into())]);
+
+        // a fork copies only the clients it's asked for
+        let mut fork = engine.fork(&[2, 9]).await.unwrap();
+        let clients = fork.balances(OutputOrder::ClientId).await.unwrap();
+        assert_eq!(clients.iter().map(|b| b.client).collect::<Vec<_>>(), [2]);
+
+        // neither the engine nor its history were touched, so the dispute can still be applied
+        assert_eq!(engine.balances(OutputOrder::ClientId).await.unwrap(), before);
+        let outcome = engine.submit_record(Record::from_csv_line("dispute, 1, 1,").unwrap());
+        assert_eq!(outcome.await.unwrap().await.unwrap(), Ok(()));
+    }
+
+    #[test]
+    fn load_state_of_unsupported_version() {
+        let Err(EngineError::StateError(error)) =
+            Engine::load_state(r#"{"version":2,"clients":[]}"#.as_bytes())
+        else {
+            panic!();
+        };
+        assert_eq!(error, "Unsupported state version: 2. Expected 1");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn metrics_of_processed_records() {
+        let input_str = r#"type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, 2, 2, 2.0
+withdrawal, 1, 3, 5.0
+dispute, 1, 1,
+dispute, 2, 2,
+chargeback, 2, 2,
+deposit, 2, 4, 1.0"#;
+
+        let mut engine = Engine::new();
+        for record in Record::from_csv(input_str.as_bytes()).unwrap() {
+            engine.process_record(record).await.unwrap();
+        }
+        assert!(engine
+            .process_record(Record::from_csv_line("deposit, 1, 5,").unwrap())
+            .await
+            .is_err());
+        // snapshots go through the same queues, so all transactions are processed after them
+        engine.balances(OutputOrder::Unsorted).await.unwrap();
+
+        let metrics = engine.metrics().render();
+        for line in [
+            "engine_records_total{type=\"deposit\"} 3",
+            "engine_records_total{type=\"dispute\"} 2",
+            "engine_records_total{type=\"resolve\"} 0",
+            "engine_invalid_records_total 1",
+            "engine_rejections_total{reason=\"insufficient_funds\"} 1",
+            "engine_rejections_total{reason=\"account_locked\"} 1",
+            "engine_clients 2",
+            "engine_open_disputes 1",
+            "engine_locked_accounts 1",
+            "engine_transaction_latency_seconds_count 7",
+            "engine_client_queue_depth{client=\"1\"} 0",
+            "engine_client_latency_seconds_count{client=\"2\"} 4",
+        ] {
+            assert!(metrics.lines().any(|l| l == line), "missing {line} in:\n{metrics}");
+        }
+
+        let top = engine.metrics().top_clients(1);
+        assert_eq!(
+            top,
+            [ClientVolume { client: 2, transactions: 4, volume: rust_decimal::Decimal::TWO }]
+        );
+        let clients = engine.metrics().top_clients(5).into_iter().map(|c| c.client);
+        assert_eq!(clients.collect::<Vec<_>>(), [2, 1]);
+    }
+
+    #[test]
+    fn records_know_their_lines() {
+        let input_str = "# header\ntype, client, tx, amount\n# comment\ndeposit, 1, 1, \
+                         1.0\n\n\ndispute, 1, 1,\n";
+
+        let lines = Record::from_csv(input_str.as_bytes())
+            .unwrap()
+            .into_iter()
+            .map(|r| r.line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [Some(4), Some(7)]);
+        assert_eq!(Record::from_csv_line("deposit, 1, 1, 1.0").unwrap().at_line(7).line, Some(7));
+    }
+
+    #[tokio::test]
+    async fn run_stats() {
+        let input_str = r#"type, client, tx, amount
+deposit, 1, 1, 1.5
+withdrawal, 1, 2, 5.0
+deposit, 2, 3, 2.0
+dispute, 2, 3,
+chargeback, 2, 3,
+withdrawal, 2, 4, 0.5"#;
+
+        let stats = internal_process_transactions(
+            input_str.as_bytes(),
+            std::io::sink(),
+            &ProcessOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(stats.rows, 6);
+        assert_eq!(stats.rows_by_type["deposit"], 2);
+        assert_eq!(stats.accepted, 4);
+        assert_eq!(stats.rejected["insufficient_funds"], 1);
+        assert_eq!(stats.rejected["account_locked"], 1);
+        assert_eq!(stats.rejected_total(), 2);
+        assert_eq!((stats.clients, stats.locked_accounts, stats.open_disputes), (2, 1, 0));
+        assert_eq!(stats.funds_in.to_string(), "3.5");
+        assert_eq!(stats.funds_out.to_string(), "2");
+    }
+
+    #[tokio::test]
+    async fn chargebacks_without_locking_are_not_counted_as_locked() {
+        let input_str = r#"type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, 1, 2, 2.0
+dispute, 1, 1,
+chargeback, 1, 1,
+dispute, 1, 2,
+chargeback, 1, 2,"#;
+
+        let config =
+            EngineConfig::from_toml("[locked_accounts]\nlock_on_chargeback = false").unwrap();
+        let options = ProcessOptions { config, ..Default::default() };
+        let mut output = Vec::new();
+        let stats = internal_process_transactions(input_str.as_bytes(), &mut output, &options)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held,total,locked\n1,0,0,0,false\n"
+        );
+        assert_eq!((stats.clients, stats.locked_accounts, stats.open_disputes), (1, 0, 0));
+    }
+
+    #[tokio::test]
+    async fn audit_trail_of_wallet_changes() {
+        let input_str = r#"type, client, tx, amount
+deposit, 1, 1, 2.0
+withdrawal, 1, 2, 5.0
+deposit, 2, 3, 1.0
+dispute, 1, 1,
+chargeback, 1, 1,"#;
+
+        let log_path = std::env::temp_dir().join(format!("audit_{}.jsonl", std::process::id()));
+        let options = ProcessOptions { audit_log: Some(log_path.clone()), ..Default::default() };
+        // only the last two changes of each wallet are kept
+        let engine = new_engine(&options).unwrap().with_audit_trails(2);
+        let mut engine = read_transactions(input_str.as_bytes(), engine, None).await.unwrap();
+
+        let trail = engine.audit_trail(1).await.unwrap().unwrap();
+        let changes = trail
+            .iter()
+            .map(|e| (e.tx, e.line, e.ty, e.before.held, e.after.held, e.after.locked))
+            .collect::<Vec<_>>();
+        let held = |value: i64| rust_decimal::Decimal::from(value);
+        assert_eq!(
+            changes,
+            [
+                (1, Some(5), TxType::Dispute, held(0), held(2), false),
+                (1, Some(6), TxType::Chargeback, held(2), held(0), true),
+            ]
+        );
+        assert!(engine.audit_trail(3).await.unwrap().is_none());
+
+        // the log has changes of all clients, in the order they happened for each of them
+        engine.client_states(&[1, 2]).await.unwrap();
+        let file = std::io::BufReader::new(std::fs::File::open(&log_path).unwrap());
+        let logged = AuditEntry::read_log(file).unwrap();
+        std::fs::remove_file(&log_path).unwrap();
+        let logged_1 = logged.iter().filter(|e| e.client == 1).cloned().collect::<Vec<_>>();
+        assert_eq!(logged_1.len(), 3);
+        assert_eq!(logged_1[1..], trail);
+        assert_eq!(logged.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn audit_log_write_error_is_returned() {
+        struct FailingWriter;
+        impl std::io::Write for FailingWriter {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("disk full"))
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let engine = Engine::new();
+        engine.set_audit_log(FailingWriter);
+        let input_str = "type,client,tx,amount\ndeposit,1,1,2.0";
+        let mut engine = read_transactions(input_str.as_bytes(), engine, None).await.unwrap();
+
+        let mut output = Vec::new();
+        let result = engine
+            .print_wallets(&mut output, OutputOrder::ClientId, &OutputSchema::default())
+            .await;
+        let Err(EngineError::AuditError(error)) = result else {
+            panic!("expected audit error, got {result:?}");
+        };
+        assert_eq!(error, "Failed to write audit log: disk full");
+    }
+
+    #[test]
+    fn validation_reports_every_problem() {
+        let input_str = r#"type, client, tx, amount
+# partner export
+deposit, 1, 1, 1.0
+withdrawal, 2, ads, 3.0
+deposit, 1, 2,
+deposit, 2, 1, 2.0
+
+dispute, 1, 7,
+dispute, 2, 1,
+withdrawal, 1, 3, 0.5
+resolve, 1, 3,
+dispute, 1, 1,
+unknown, 1, 4, 1.0
+withdrawal, 1, 5, 1.0, 2.0
+deposit, 3, 6, 5e28
+deposit, 3, 7, 5e28"#;
+
+        let report = validate_transactions(input_str.as_bytes()).unwrap();
+        let issues = report.issues.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            issues,
+            [
+                "line 4: field 2: invalid digit found in string",
+                "line 5: The amount field is missing for deposit transaction in csv",
+                "line 6: duplicate transaction id 1, first used at line 3",
+                "line 8: dispute of unknown transaction 7",
+                "line 9: dispute of transaction 1 which belongs to client 1",
+                "line 11: resolve of withdrawal 3, only deposits can be disputed",
+                "line 13: Unknown transaction type: \"unknown\"",
+                "line 14: found record with 5 fields, but the previous record has 4 fields",
+                "line 16: deposit would bring the balance of client 3 over the largest supported \
+                 amount",
+            ]
+        );
+        assert_eq!(report.rows, 13);
+        assert!(!report.is_valid());
+
+        let valid_str = "type,client,tx,amount\ndeposit,1,1,1.0\ndispute,1,1,\nresolve,1,1,";
+        assert!(validate_transactions(valid_str.as_bytes()).unwrap().is_valid());
+
+        // lines of a quoted field are data, even when they look like comments
+        let quoted_str = "type,client,tx,amount\n\"deposit\n# not a comment\",1,1,1.0\n# \
+                          comment\n\nwithdrawal,1,x,1";
+        let report = validate_transactions(quoted_str.as_bytes()).unwrap();
+        let issues = report.issues.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            issues,
+            [
+                "line 2: Unknown transaction type: \"deposit\n# not a comment\"",
+                "line 6: field 2: invalid digit found in string",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn skip_invalid_rows() {
+        let input_str = r#"type, client, tx, amount
+deposit, 1, 1, 1.0
+withdrawal, 2, ads, 3.0
+deposit, 1, 2,
+deposit, 2, 3, 2.0
+unknown, 1, 4, 1.0
+withdrawal, 1, 5, 0.5"#;
+
+        let limit = InvalidRowsLimit { max_count: Some(3), max_ratio: None };
+        let options = ProcessOptions {
+            order: OutputOrder::ClientId,
+            skip_invalid: Some(limit),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        let stats = internal_process_transactions(input_str.as_bytes(), &mut output, &options)
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held,total,locked\n1,0.5,0,0.5,false\n2,2,0,2,false\n"
+        );
+        assert_eq!((stats.rows, stats.invalid_rows), (6, 3));
+
+        for limit in [
+            InvalidRowsLimit { max_count: Some(2), max_ratio: None },
+            InvalidRowsLimit { max_count: None, max_ratio: Some(0.4) },
+        ] {
+            let options = ProcessOptions { skip_invalid: Some(limit), ..Default::default() };
+            let Err(InputFileError(error)) =
+                internal_process_transactions(input_str.as_bytes(), std::io::sink(), &options)
+                    .await
+            else {
+                panic!();
+            };
+            assert!(error.starts_with("too many invalid rows: 3 of"), "{error}");
+        }
+
+        let Err(CsvError(_)) = test_process_transaction(input_str.as_bytes()).await else {
+            panic!();
+        };
+    }
+
+    #[tokio::test]
+    async fn engine_policies_from_config() {
+        let config = EngineConfig::from_toml(
+            r#"
+precision = 2
+
+[disputes]
+require_available_funds = false
+
+[locked_accounts]
+accept_deposits = true
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.channel_capacity, 32);
+        assert!(config.locked_accounts.lock_on_chargeback);
+        assert_eq!(
+            EngineConfig::from_toml(&EngineConfig::default().to_toml()).unwrap(),
+            Default::default()
+        );
+
+        let input_str = r#"type, client, tx, amount
+deposit, 1, 1, 1.2345
+withdrawal, 1, 2, 1.0
+dispute, 1, 1,
+deposit, 2, 3, 2.0
+dispute, 2, 3,
+chargeback, 2, 3,
+deposit, 2, 4, 0.5
+withdrawal, 2, 5, 0.5"#;
+
+        let options = ProcessOptions { order: OutputOrder::ClientId, config, ..Default::default() };
+        let mut output = Vec::new();
+        internal_process_transactions(input_str.as_bytes(), &mut output, &options).await.unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held,total,locked\n1,-1,1.23,0.23,false\n2,0.5,0,0.5,true\n"
+        );
+
+        for (toml, error) in [
+            ("channel_capacity = 0", "channel_capacity must be at least 1"),
+            (
+                "channel_capacity = 4611686018427387904",
+                "channel_capacity must be at most 2305843009213693951, got 4611686018427387904",
+            ),
+            ("precision = 9", "precision must be at most 8, got 9"),
+        ] {
+            let Err(ConfigError(e)) = EngineConfig::from_toml(toml) else {
+                panic!();
+            };
+            assert_eq!(e, error);
+        }
+        assert!(matches!(EngineConfig::from_toml("unknown = 1"), Err(ConfigError(_))));
+    }
 }
@@ -0,0 +1,342 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use rust_decimal::{prelude::FromPrimitive, Decimal};
+
+use crate::{
+    engine::{ClientId, TxRejection, TxType},
+    RunStats,
+};
+
+/// upper bounds (in seconds) of transaction latency histogram buckets
+const LATENCY_BUCKETS: [f64; 8] = [0.000_01, 0.000_05, 0.000_1, 0.000_5, 0.001, 0.005, 0.01, 0.1];
+
+/// Operational metrics of the engine, rendered in Prometheus text format or summarized as
+/// [`RunStats`].
+///
+/// Counters are updated by the engine (records) and by the client tasks (outcomes), so they can
+/// be read at any time without asking the clients.
+#[derive(Debug)]
+pub struct Metrics {
+    started_at: Instant,
+    records: [AtomicU64; TxType::ALL.len()],
+    invalid_records: AtomicU64,
+    rejections: [AtomicU64; TxRejection::ALL.len()],
+    open_disputes: AtomicI64,
+    locked_accounts: AtomicI64,
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    latency_sum_nanos: AtomicU64,
+    latency_count: AtomicU64,
+    /// sums of moved funds, as bits of f64
+    funds_in: AtomicU64,
+    funds_out: AtomicU64,
+    clients: Mutex<BTreeMap<ClientId, Arc<ClientMetrics>>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            started_at: Instant::now(),
+            records: Default::default(),
+            invalid_records: Default::default(),
+            rejections: Default::default(),
+            open_disputes: Default::default(),
+            locked_accounts: Default::default(),
+            latency_buckets: Default::default(),
+            latency_sum_nanos: Default::default(),
+            latency_count: Default::default(),
+            funds_in: Default::default(),
+            funds_out: Default::default(),
+            clients: Default::default(),
+        }
+    }
+}
+
+/// Metrics of a single client, shared by the engine (sending side) and the client task
+#[derive(Debug, Default)]
+pub(crate) struct ClientMetrics {
+    queued: AtomicI64,
+    processed: AtomicU64,
+    latency_sum_nanos: AtomicU64,
+    /// funds moved by applied deposits and withdrawals, as bits of f64
+    volume: AtomicU64,
+}
+
+/// Client with its traffic so far, see [`Metrics::top_clients`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientVolume {
+    pub client: ClientId,
+    /// processed transactions, including rejected ones
+    pub transactions: u64,
+    /// funds moved by applied deposits and withdrawals
+    pub volume: Decimal,
+}
+
+impl ClientMetrics {
+    pub(crate) fn queued(&self) {
+        self.queued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn received(&self) {
+        self.queued.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Metrics {
+    pub(crate) fn client(&self, id: ClientId) -> Arc<ClientMetrics> {
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        clients.entry(id).or_default().clone()
+    }
+
+    pub(crate) fn record(&self, ty: Option<TxType>) {
+        match ty {
+            Some(ty) => self.records[ty as usize].fetch_add(1, Ordering::Relaxed),
+            None => self.invalid_records.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    /// transaction was processed by the client task. `amount` is the amount of funds it moved,
+    /// `locked` whether it locked the account
+    pub(crate) fn processed(
+        &self,
+        client: &ClientMetrics,
+        ty: TxType,
+        outcome: Result<(), TxRejection>,
+        amount: Option<f32>,
+        locked: bool,
+        latency: Duration,
+    ) {
+        let amount = f64::from(amount.unwrap_or_default());
+        match (ty, outcome) {
+            (_, Err(rejection)) => {
+                self.rejections[rejection as usize].fetch_add(1, Ordering::Relaxed);
+            },
+            (TxType::Deposit, Ok(())) => {
+                add_f64(&self.funds_in, amount);
+                add_f64(&client.volume, amount);
+            },
+            (TxType::Withdrawal, Ok(())) => {
+                add_f64(&self.funds_out, amount);
+                add_f64(&client.volume, amount);
+            },
+            (TxType::Dispute, Ok(())) => {
+                self.open_disputes.fetch_add(1, Ordering::Relaxed);
+            },
+            (TxType::Resolve, Ok(())) => {
+                self.open_disputes.fetch_sub(1, Ordering::Relaxed);
+            },
+            (TxType::Chargeback, Ok(())) => {
+                self.open_disputes.fetch_sub(1, Ordering::Relaxed);
+                self.locked_accounts.fetch_add(i64::from(locked), Ordering::Relaxed);
+                add_f64(&self.funds_out, amount);
+            },
+        }
+
+        let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+        let secs = latency.as_secs_f64();
+        for (bucket, bound) in self.latency_buckets.iter().zip(LATENCY_BUCKETS) {
+            if secs <= bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.latency_sum_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.latency_count.fetch_add(1, Ordering::Relaxed);
+
+        client.processed.fetch_add(1, Ordering::Relaxed);
+        client.latency_sum_nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    /// client restored from saved state
+    pub(crate) fn restored(&self, open_disputes: usize, locked: bool) {
+        self.open_disputes.fetch_add(open_disputes as i64, Ordering::Relaxed);
+        self.locked_accounts.fetch_add(i64::from(locked), Ordering::Relaxed);
+    }
+
+    /// summary of everything counted since the engine was created
+    pub fn stats(&self) -> RunStats {
+        let rows_by_type = TxType::ALL
+            .iter()
+            .map(|ty| (ty.to_string(), self.records[*ty as usize].load(Ordering::Relaxed)))
+            .collect::<BTreeMap<_, _>>();
+        let rejected = TxRejection::ALL
+            .iter()
+            .map(|r| (r.code().to_string(), self.rejections[*r as usize].load(Ordering::Relaxed)))
+            .collect::<BTreeMap<_, _>>();
+
+        let invalid_rows = self.invalid_records.load(Ordering::Relaxed);
+        let rows = rows_by_type.values().sum::<u64>() + invalid_rows;
+        let processed = self.latency_count.load(Ordering::Relaxed);
+        let elapsed_secs = self.started_at.elapsed().as_secs_f64();
+        let gauge = |value: &AtomicI64| u64::try_from(value.load(Ordering::Relaxed)).unwrap_or(0);
+
+        RunStats {
+            rows,
+            invalid_rows,
+            accepted: processed.saturating_sub(rejected.values().sum()),
+            rows_by_type,
+            rejected,
+            clients: self.clients.lock().unwrap_or_else(|e| e.into_inner()).len() as u64,
+            locked_accounts: gauge(&self.locked_accounts),
+            open_disputes: gauge(&self.open_disputes),
+            funds_in: load_funds(&self.funds_in),
+            funds_out: load_funds(&self.funds_out),
+            elapsed_secs,
+            rows_per_sec: if elapsed_secs > 0.0 { rows as f64 / elapsed_secs } else { 0.0 },
+        }
+    }
+
+    /// `count` clients which moved the most funds so far, the biggest first
+    pub fn top_clients(&self, count: usize) -> Vec<ClientVolume> {
+        // client tasks register under the lock, so it's held only to copy the handles out
+        let clients = {
+            let clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+            clients.iter().map(|(id, client)| (*id, client.clone())).collect::<Vec<_>>()
+        };
+
+        let mut top = clients
+            .into_iter()
+            .map(|(id, client)| (id, f64::from_bits(client.volume.load(Ordering::Relaxed)), client))
+            .collect::<Vec<_>>();
+        let by_volume = |a: &(ClientId, f64, _), b: &(ClientId, f64, _)| {
+            b.1.total_cmp(&a.1).then(a.0.cmp(&b.0))
+        };
+        // only the top ones are sorted
+        if top.len() > count {
+            top.select_nth_unstable_by(count, by_volume);
+            top.truncate(count);
+        }
+        top.sort_by(by_volume);
+
+        top.into_iter()
+            .map(|(client, volume, metrics)| ClientVolume {
+                client,
+                transactions: metrics.processed.load(Ordering::Relaxed),
+                volume: funds(volume),
+            })
+            .collect()
+    }
+
+    /// render all metrics in Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+
+        header(&mut out, "engine_records_total", "counter", "Valid records by transaction type");
+        for ty in TxType::ALL {
+            let value = self.records[ty as usize].load(Ordering::Relaxed);
+            let _ = writeln!(out, "engine_records_total{{type=\"{ty}\"}} {value}");
+        }
+
+        header(
+            &mut out,
+            "engine_invalid_records_total",
+            "counter",
+            "Records which failed validation",
+        );
+        let _ = writeln!(
+            out,
+            "engine_invalid_records_total {}",
+            self.invalid_records.load(Ordering::Relaxed)
+        );
+
+        header(&mut out, "engine_rejections_total", "counter", "Rejected transactions by reason");
+        for rejection in TxRejection::ALL {
+            let value = self.rejections[rejection as usize].load(Ordering::Relaxed);
+            let _ =
+                writeln!(out, "engine_rejections_total{{reason=\"{}\"}} {value}", rejection.code());
+        }
+
+        header(&mut out, "engine_clients", "gauge", "Clients known to the engine");
+        let _ = writeln!(out, "engine_clients {}", clients.len());
+
+        header(&mut out, "engine_open_disputes", "gauge", "Disputes not resolved nor charged back");
+        let _ =
+            writeln!(out, "engine_open_disputes {}", self.open_disputes.load(Ordering::Relaxed));
+
+        header(&mut out, "engine_locked_accounts", "gauge", "Accounts locked by a chargeback");
+        let _ = writeln!(
+            out,
+            "engine_locked_accounts {}",
+            self.locked_accounts.load(Ordering::Relaxed)
+        );
+
+        header(
+            &mut out,
+            "engine_transaction_latency_seconds",
+            "histogram",
+            "Time from accepting a transaction till the client task processed it",
+        );
+        for (bucket, bound) in self.latency_buckets.iter().zip(LATENCY_BUCKETS) {
+            let _ = writeln!(
+                out,
+                "engine_transaction_latency_seconds_bucket{{le=\"{bound}\"}} {}",
+                bucket.load(Ordering::Relaxed)
+            );
+        }
+        let count = self.latency_count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "engine_transaction_latency_seconds_bucket{{le=\"+Inf\"}} {count}");
+        let _ = writeln!(
+            out,
+            "engine_transaction_latency_seconds_sum {}",
+            nanos_to_secs(self.latency_sum_nanos.load(Ordering::Relaxed))
+        );
+        let _ = writeln!(out, "engine_transaction_latency_seconds_count {count}");
+
+        header(
+            &mut out,
+            "engine_client_queue_depth",
+            "gauge",
+            "Messages waiting for the client task",
+        );
+        for (id, client) in clients.iter() {
+            let queued = client.queued.load(Ordering::Relaxed);
+            let _ = writeln!(out, "engine_client_queue_depth{{client=\"{id}\"}} {queued}");
+        }
+
+        header(
+            &mut out,
+            "engine_client_latency_seconds",
+            "summary",
+            "Time from accepting a transaction till the client task processed it, by client",
+        );
+        for (id, client) in clients.iter() {
+            let sum = nanos_to_secs(client.latency_sum_nanos.load(Ordering::Relaxed));
+            let count = client.processed.load(Ordering::Relaxed);
+            let _ = writeln!(out, "engine_client_latency_seconds_sum{{client=\"{id}\"}} {sum}");
+            let _ = writeln!(out, "engine_client_latency_seconds_count{{client=\"{id}\"}} {count}");
+        }
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, ty: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {ty}");
+}
+
+fn add_f64(value: &AtomicU64, amount: f64) {
+    // the closure always returns Some, so it can't fail
+    let _ = value.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+        Some((f64::from_bits(bits) + amount).to_bits())
+    });
+}
+
+fn load_funds(value: &AtomicU64) -> Decimal {
+    funds(f64::from_bits(value.load(Ordering::Relaxed)))
+}
+
+fn funds(funds: f64) -> Decimal {
+    Decimal::from_f64(funds).unwrap_or_default().round_dp(4)
+}
+
+fn nanos_to_secs(nanos: u64) -> f64 {
+    Duration::from_nanos(nanos).as_secs_f64()
+}
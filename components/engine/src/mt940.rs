@@ -0,0 +1,133 @@
+use std::{collections::HashMap, io::Read};
+
+use crate::{
+    engine::{ClientId, TxId},
+    EngineError, Record,
+};
+
+/// Maps bank account (value of the `:25:` tag) to the client which owns it
+pub(crate) type Accounts = HashMap<String, ClientId>;
+
+#[derive(Debug, serde::Deserialize)]
+struct AccountRecord {
+    account: String,
+    #[serde(rename = "client")]
+    client_id: ClientId,
+}
+
+/// read accounts lookup file. It's a csv file with `account,client` header
+pub(crate) fn read_accounts<R: Read>(io_reader: R) -> Result<Accounts, EngineError> {
+    let mut rdr =
+        csv::ReaderBuilder::new().trim(csv::Trim::All).comment(Some(b'#')).from_reader(io_reader);
+
+    let mut accounts = Accounts::new();
+    for result in rdr.deserialize() {
+        let r: AccountRecord = result?;
+        accounts.insert(r.account, r.client_id);
+    }
+    Ok(accounts)
+}
+
+/// Parse SWIFT MT940 statements into deposit and withdrawal records.
+///
+/// Only `:25:` (account identification) and `:61:` (statement line) tags are used. Credits become
+/// deposits, debits become withdrawals and reversals are mapped the other way round. The customer
+/// reference of the statement line must be a number, it's used as the transaction id.
+pub(crate) fn parse_records<R: Read>(
+    mut io_reader: R,
+    accounts: &Accounts,
+) -> Result<Vec<Record>, EngineError> {
+    let mut input = String::new();
+    io_reader.read_to_string(&mut input)?;
+
+    let mut records = Vec::new();
+    let mut client_id = None;
+
+    for (idx, line) in input.lines().enumerate() {
+        let line_no = idx + 1;
+        let line = line.trim();
+
+        if let Some(account) = line.strip_prefix(":25:") {
+            let Some(id) = accounts.get(account.trim()) else {
+                return Err(mt940_error(line_no, format!("Unknown account: \"{account}\"")));
+            };
+            client_id = Some(*id);
+        } else if let Some(statement_line) = line.strip_prefix(":61:") {
+            let Some(client_id) = client_id else {
+                return Err(mt940_error(line_no, "Statement line before account (:25:) tag"));
+            };
+            let (ty, amount, tx_id) = parse_statement_line(statement_line)
+                .map_err(|e| mt940_error(line_no, format!("{e} in \"{line}\"")))?;
+
+            records.push(Record {
+                ty: ty.to_string(),
+                client_id,
+                tx_id,
+                amount: Some(amount),
+                line: Some(line_no as u64),
+            });
+        }
+    }
+
+    Ok(records)
+}
+
+/// parse value of `:61:` tag: `YYMMDD[MMDD]<mark>[funds code]<amount><type><reference>[//bank ref]`
+fn parse_statement_line(line: &str) -> Result<(&'static str, f32, TxId), &'static str> {
+    let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+
+    // value date is mandatory, entry date is optional
+    let Some((value_date, mut rest)) = split_at(line, 6) else {
+        return Err("Missing value date");
+    };
+    if !is_digits(value_date) {
+        return Err("Incorrect value date");
+    }
+    if let Some((entry_date, after)) = split_at(rest, 4) {
+        if is_digits(entry_date) {
+            rest = after;
+        }
+    }
+
+    // debit/credit mark. Reversal of credit is a debit and vice versa
+    let (ty, rest) = if let Some(rest) = rest.strip_prefix("RC") {
+        ("withdrawal", rest)
+    } else if let Some(rest) = rest.strip_prefix("RD") {
+        ("deposit", rest)
+    } else if let Some(rest) = rest.strip_prefix('C') {
+        ("deposit", rest)
+    } else if let Some(rest) = rest.strip_prefix('D') {
+        ("withdrawal", rest)
+    } else {
+        return Err("Incorrect debit/credit mark");
+    };
+
+    // optional funds code (third character of currency code)
+    let rest = rest.strip_prefix(|c: char| c.is_ascii_alphabetic()).unwrap_or(rest);
+
+    let amount_len = rest.find(|c: char| !c.is_ascii_digit() && c != ',').unwrap_or(rest.len());
+    let (amount, rest) = rest.split_at(amount_len);
+    let Ok(amount) = amount.replace(',', ".").parse::<f32>() else {
+        return Err("Incorrect amount");
+    };
+
+    // transaction type identification code, eg. NTRF
+    let Some((_, rest)) = split_at(rest, 4) else {
+        return Err("Missing transaction type");
+    };
+
+    let reference = rest.split("//").next().unwrap_or_default().trim();
+    let Ok(tx_id) = reference.parse::<TxId>() else {
+        return Err("Customer reference is not a transaction id");
+    };
+
+    Ok((ty, amount, tx_id))
+}
+
+fn split_at(s: &str, mid: usize) -> Option<(&str, &str)> {
+    Some((s.get(..mid)?, s.get(mid..)?))
+}
+
+fn mt940_error(line_no: usize, msg: impl Into<String>) -> EngineError {
+    EngineError::Mt940Error(format!("line {line_no}: {}", msg.into()))
+}
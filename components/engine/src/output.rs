@@ -0,0 +1,237 @@
+use std::{fmt::Display, str::FromStr};
+
+use rust_decimal::Decimal;
+
+use crate::{
+    engine::{ClientId, ClientState, Wallet},
+    EngineError,
+};
+
+/// Order in which client wallets are written out once all transactions are processed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputOrder {
+    /// whatever order the clients map gives us. Cheapest, but differs between runs
+    #[default]
+    Unsorted,
+    /// ascending numeric client id
+    ClientId,
+    /// descending total funds, ties are broken by ascending client id
+    Balance,
+}
+
+impl OutputOrder {
+    pub(crate) fn sort(&self, states: &mut [(ClientId, ClientState)]) {
+        match self {
+            Self::Unsorted => {},
+            Self::ClientId => states.sort_by_key(|(id, _)| *id),
+            Self::Balance => states.sort_by(|(id_a, state_a), (id_b, state_b)| {
+                let (total_a, total_b) = (state_a.wallet().total(), state_b.wallet().total());
+                total_b.total_cmp(&total_a).then(id_a.cmp(id_b))
+            }),
+        }
+    }
+}
+
+impl FromStr for OutputOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unsorted" => Ok(Self::Unsorted),
+            "client" => Ok(Self::ClientId),
+            "balance" => Ok(Self::Balance),
+            _ => Err(format!(
+                "Unknown output order: \"{s}\". Expected one of: unsorted, client, balance"
+            )),
+        }
+    }
+}
+
+impl Display for OutputOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Unsorted => "unsorted",
+            Self::ClientId => "client",
+            Self::Balance => "balance",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// Funds of a single client, rounded the same way as in the wallets output
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Balance {
+    pub client: ClientId,
+    #[serde(with = "rust_decimal::serde::float")]
+    pub available: Decimal,
+    #[serde(with = "rust_decimal::serde::float")]
+    pub held: Decimal,
+    #[serde(with = "rust_decimal::serde::float")]
+    pub total: Decimal,
+    pub locked: bool,
+}
+
+impl Balance {
+    pub(crate) fn new(id: ClientId, state: &ClientState, precision: u32) -> Self {
+        let wallet = state.wallet();
+        Self {
+            client: id,
+            available: Wallet::round(wallet.available(), precision),
+            held: Wallet::round(wallet.held(), precision),
+            total: Wallet::round(wallet.total(), precision),
+            locked: wallet.locked(),
+        }
+    }
+}
+
+/// Single column of the wallets output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Client,
+    Available,
+    Held,
+    Total,
+    Locked,
+    /// id of the last transaction which changed the client wallet
+    LastTx,
+    /// number of deposits which are disputed and neither resolved nor charged back
+    OpenDisputes,
+    /// currency code of the funds, the same for every client
+    Currency,
+}
+
+impl Column {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Client => "client",
+            Self::Available => "available",
+            Self::Held => "held",
+            Self::Total => "total",
+            Self::Locked => "locked",
+            Self::LastTx => "last_tx",
+            Self::OpenDisputes => "open_disputes",
+            Self::Currency => "currency",
+        }
+    }
+}
+
+impl FromStr for Column {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "client" => Ok(Self::Client),
+            "available" => Ok(Self::Available),
+            "held" => Ok(Self::Held),
+            "total" => Ok(Self::Total),
+            "locked" => Ok(Self::Locked),
+            "last_tx" => Ok(Self::LastTx),
+            "open_disputes" => Ok(Self::OpenDisputes),
+            "currency" => Ok(Self::Currency),
+            _ => Err(format!(
+                "Unknown column: \"{s}\". Expected one of: last_tx, open_disputes, currency"
+            )),
+        }
+    }
+}
+
+impl Display for Column {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Columns of the wallets output.
+///
+/// Each schema version has a fixed set of base columns which are always printed, in this order.
+/// Extra columns can be selected on top of them and are appended after the base ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputSchema {
+    version: u32,
+    columns: Vec<Column>,
+    currency: String,
+}
+
+impl OutputSchema {
+    pub const DEFAULT_CURRENCY: &'static str = "USD";
+    pub const LATEST_VERSION: u32 = 1;
+
+    pub fn new(version: u32) -> Result<Self, EngineError> {
+        let columns = match version {
+            1 => {
+                vec![Column::Client, Column::Available, Column::Held, Column::Total, Column::Locked]
+            },
+            _ => {
+                return Err(EngineError::OutputSchemaError(format!(
+                    "Unsupported output schema version: {version}. Latest version is {}",
+                    Self::LATEST_VERSION
+                )))
+            },
+        };
+
+        Ok(Self { version, columns, currency: Self::DEFAULT_CURRENCY.to_string() })
+    }
+
+    /// append extra column. Columns which are already part of the schema are ignored
+    pub fn with_column(mut self, column: Column) -> Self {
+        if !self.columns.contains(&column) {
+            self.columns.push(column);
+        }
+        self
+    }
+
+    /// currency code printed in the currency column
+    pub fn with_currency(mut self, currency: &str) -> Self {
+        self.currency = currency.to_string();
+        self
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    pub(crate) fn header(&self) -> String {
+        self.columns.iter().map(Column::name).collect::<Vec<_>>().join(",")
+    }
+
+    /// row of the client with amounts rounded to `precision` decimal places
+    pub(crate) fn row(&self, id: ClientId, state: &ClientState, precision: u32) -> String {
+        let wallet = state.wallet();
+        let round = |amount| Wallet::round(amount, precision).to_string();
+        let fields = self.columns.iter().map(|column| match column {
+            Column::Client => id.to_string(),
+            Column::Available => round(wallet.available()),
+            Column::Held => round(wallet.held()),
+            Column::Total => round(wallet.total()),
+            Column::Locked => wallet.locked().to_string(),
+            Column::LastTx => state.last_tx().map(|id| id.to_string()).unwrap_or_default(),
+            Column::OpenDisputes => state.open_disputes().to_string(),
+            Column::Currency => self.currency.clone(),
+        });
+
+        // the currency comes from the user, so it's quoted if it has a comma or a quote
+        let mut writer = csv::WriterBuilder::new()
+            .terminator(csv::Terminator::Any(b'\n'))
+            .from_writer(Vec::new());
+        // writing to memory can't fail
+        let _ = writer.write_record(fields);
+        let mut row =
+            String::from_utf8(writer.into_inner().unwrap_or_default()).unwrap_or_default();
+        row.pop();
+        row
+    }
+}
+
+impl Default for OutputSchema {
+    fn default() -> Self {
+        Self::new(Self::LATEST_VERSION).expect("latest schema version is always supported")
+    }
+}
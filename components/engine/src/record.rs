@@ -1,12 +1,202 @@
-use crate::engine::{ClientId, TxId};
+use std::{
+    io::{self, Read},
+    sync::{Arc, Mutex},
+};
 
-#[derive(Debug, serde::Deserialize)]
-pub(crate) struct Record {
+use csv::Trim;
+
+use crate::{
+    engine::{ClientId, TxId},
+    EngineError,
+};
+
+/// Single row of the input: `type,client,tx,amount`
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Record {
     #[serde(rename = "type")]
-    pub(crate) ty: String,
+    pub ty: String,
     #[serde(rename = "client")]
-    pub(crate) client_id: ClientId,
+    pub client_id: ClientId,
     #[serde(rename = "tx")]
-    pub(crate) tx_id: TxId,
-    pub(crate) amount: Option<f32>,
+    pub tx_id: TxId,
+    pub amount: Option<f32>,
+    /// line of the input the record comes from, if known. Used only to trace the record
+    #[serde(skip)]
+    pub line: Option<u64>,
+}
+
+impl Record {
+    /// read all records from csv with `type,client,tx,amount` header
+    pub fn from_csv<R: Read>(io_reader: R) -> Result<Vec<Record>, EngineError> {
+        Self::csv_records(io_reader)?.collect()
+    }
+
+    /// iterate over records of csv with `type,client,tx,amount` header. Each record knows its line
+    pub(crate) fn csv_records<R: Read>(
+        io_reader: R,
+    ) -> Result<impl Iterator<Item = Result<Record, EngineError>>, EngineError> {
+        let (mut rdr, lines) = Self::csv_reader(io_reader);
+        let headers = rdr.headers()?.clone();
+
+        Ok(rdr.into_records().map(move |row| {
+            let row = row?;
+            let mut record: Record = row.deserialize(Some(&headers))?;
+            record.line = row.position().map(|pos| lines.input_line(pos.line()));
+            Ok(record)
+        }))
+    }
+
+    /// iterate over rows of csv with `type,client,tx,amount` header, each with its input line.
+    /// Rows which aren't records are errors without the csv position, so they can be reported
+    /// with the line and skipped
+    pub fn csv_rows<R: Read>(
+        io_reader: R,
+    ) -> Result<impl Iterator<Item = (Option<u64>, Result<Record, EngineError>)>, EngineError> {
+        let (mut rdr, lines) = Self::csv_reader(io_reader);
+        let headers = rdr.headers()?.clone();
+
+        Ok(rdr.into_records().map(move |row| {
+            let position = match &row {
+                Ok(row) => row.position(),
+                Err(e) => e.position(),
+            };
+            let line = position.map(|pos| lines.input_line(pos.line()));
+            let record = row
+                .and_then(|row| row.deserialize::<Record>(Some(&headers)))
+                .map(|record| Record { line, ..record })
+                .map_err(|e| {
+                    if e.is_io_error() {
+                        EngineError::from(e)
+                    } else {
+                        EngineError::RecordError(csv_message(&e))
+                    }
+                });
+            (line, record)
+        }))
+    }
+
+    /// parse single csv row without header, eg. `deposit,1,1,1.0`. Trailing amount field of
+    /// disputes, resolves and chargebacks can be omitted
+    pub fn from_csv_line(line: &str) -> Result<Record, EngineError> {
+        let headers = csv::StringRecord::from(vec!["type", "client", "tx", "amount"]);
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .trim(Trim::All)
+            .from_reader(line.as_bytes());
+
+        let Some(row) = rdr.records().next() else {
+            return Err(EngineError::RecordError("Empty csv row".to_string()));
+        };
+        // the position of the error would be the one of the single row reader, not of the input
+        let csv_error = |e: csv::Error| EngineError::RecordError(csv_message(&e));
+        row.map_err(csv_error)?.deserialize(Some(&headers)).map_err(csv_error)
+    }
+
+    /// set the input line of the record, eg. for a row parsed with [`Record::from_csv_line`]
+    pub fn at_line(mut self, line: u64) -> Self {
+        self.line = Some(line);
+        self
+    }
+
+    /// check if csv line is the `type,client,tx,amount` header
+    pub fn is_csv_header(line: &str) -> bool {
+        line.split(',').map(str::trim).eq(["type", "client", "tx", "amount"])
+    }
+
+    /// csv reader configured the way the engine reads its input, with the map of its lines to
+    /// lines of the input
+    pub(crate) fn csv_reader<R: Read>(io_reader: R) -> (csv::Reader<impl Read>, LineMap) {
+        let lines = LineMap::default();
+        let io_reader = LineScanner {
+            inner: io_reader,
+            line: 1,
+            state: LineState::Start,
+            lines: lines.clone(),
+        };
+        let rdr =
+            csv::ReaderBuilder::new().trim(Trim::All).comment(Some(b'#')).from_reader(io_reader);
+        (rdr, lines)
+    }
+}
+
+/// error of the csv reader without its position, which is reported as the line of the input
+pub(crate) fn csv_message(error: &csv::Error) -> String {
+    match error.kind() {
+        csv::ErrorKind::Deserialize { err, .. } => err.to_string(),
+        csv::ErrorKind::UnequalLengths { expected_len, len, .. } => {
+            format!(
+                "found record with {len} fields, but the previous record has {expected_len} fields"
+            )
+        },
+        _ => error.to_string(),
+    }
+}
+
+/// Lines of the input which are empty or start with `#`. The csv reader skips them, but a record
+/// which follows them gets the position of the first skipped line, so the positions it reports are
+/// corrected with this map
+#[derive(Clone, Default)]
+pub(crate) struct LineMap(Arc<Mutex<Vec<u64>>>);
+
+impl LineMap {
+    /// input line of a record or an error the csv reader reported at `line`
+    pub(crate) fn input_line(&self, mut line: u64) -> u64 {
+        let skipped = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        // records never start on a skipped line, so the lines skipped right before it are all
+        // consecutive. Lines inside of quoted fields are never at the reported position
+        let mut idx = skipped.partition_point(|skipped| *skipped < line);
+        while skipped.get(idx) == Some(&line) {
+            line += 1;
+            idx += 1;
+        }
+        line
+    }
+}
+
+#[derive(Clone, Copy)]
+enum LineState {
+    Start,
+    /// only `\r` so far
+    StartCr,
+    Other,
+}
+
+/// Passes the input to the csv reader untouched and notes lines which are empty or start with `#`
+struct LineScanner<R> {
+    inner: R,
+    line: u64,
+    state: LineState,
+    lines: LineMap,
+}
+
+impl<R: Read> Read for LineScanner<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        let mut skipped = Vec::new();
+        for byte in &buf[..read] {
+            self.state = match (self.state, byte) {
+                (LineState::Start | LineState::StartCr, b'\n') => {
+                    skipped.push(self.line);
+                    self.line += 1;
+                    LineState::Start
+                },
+                (_, b'\n') => {
+                    self.line += 1;
+                    LineState::Start
+                },
+                (LineState::Start, b'\r') => LineState::StartCr,
+                (LineState::Start, b'#') => {
+                    skipped.push(self.line);
+                    LineState::Other
+                },
+                _ => LineState::Other,
+            };
+        }
+
+        if !skipped.is_empty() {
+            self.lines.0.lock().unwrap_or_else(|e| e.into_inner()).extend(skipped);
+        }
+        Ok(read)
+    }
 }
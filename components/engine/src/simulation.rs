@@ -0,0 +1,36 @@
+use crate::{
+    engine::{ClientId, TxId, TxRejection, TxType},
+    Balance,
+};
+
+/// What a batch of transactions would do to the engine, found by [`crate::Engine::simulate`]
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Simulation {
+    /// transactions of the batch
+    pub transactions: usize,
+    /// balances which would change, by ascending client id
+    pub changes: Vec<BalanceChange>,
+    /// transactions which would be rejected, in the order of the batch
+    pub rejections: Vec<SimulatedRejection>,
+}
+
+/// Balance of a client before and after the batch
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct BalanceChange {
+    pub client: ClientId,
+    /// `None` if the client would be new
+    pub before: Option<Balance>,
+    pub after: Balance,
+}
+
+/// Transaction of the batch which wouldn't change the client wallet
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct SimulatedRejection {
+    pub client: ClientId,
+    pub tx: TxId,
+    /// line of the input with the transaction, if known
+    pub line: Option<u64>,
+    #[serde(rename = "type")]
+    pub ty: TxType,
+    pub rejection: TxRejection,
+}
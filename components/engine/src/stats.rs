@@ -0,0 +1,57 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Formatter},
+};
+
+use rust_decimal::Decimal;
+
+/// Summary of a run: what was read, what happened to it and how fast it went
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RunStats {
+    /// all rows read, including invalid ones
+    pub rows: u64,
+    /// valid rows by transaction type
+    pub rows_by_type: BTreeMap<String, u64>,
+    pub invalid_rows: u64,
+    /// transactions which changed a wallet
+    pub accepted: u64,
+    /// transactions which were rejected, by reason
+    pub rejected: BTreeMap<String, u64>,
+    pub clients: u64,
+    pub locked_accounts: u64,
+    pub open_disputes: u64,
+    /// sum of applied deposits
+    #[serde(with = "rust_decimal::serde::float")]
+    pub funds_in: Decimal,
+    /// sum of applied withdrawals and chargebacks
+    #[serde(with = "rust_decimal::serde::float")]
+    pub funds_out: Decimal,
+    pub elapsed_secs: f64,
+    pub rows_per_sec: f64,
+}
+
+impl RunStats {
+    pub fn rejected_total(&self) -> u64 {
+        self.rejected.values().sum()
+    }
+}
+
+impl Display for RunStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "rows: {} (invalid: {})", self.rows, self.invalid_rows)?;
+        for (ty, count) in self.rows_by_type.iter() {
+            writeln!(f, "  {ty}: {count}")?;
+        }
+        writeln!(f, "accepted: {}", self.accepted)?;
+        writeln!(f, "rejected: {}", self.rejected_total())?;
+        for (reason, count) in self.rejected.iter().filter(|(_, count)| **count > 0) {
+            writeln!(f, "  {reason}: {count}")?;
+        }
+        writeln!(f, "clients: {}", self.clients)?;
+        writeln!(f, "locked accounts: {}", self.locked_accounts)?;
+        writeln!(f, "open disputes: {}", self.open_disputes)?;
+        writeln!(f, "funds in: {}", self.funds_in)?;
+        writeln!(f, "funds out: {}", self.funds_out)?;
+        write!(f, "elapsed: {:.3}s ({:.0} rows/s)", self.elapsed_secs, self.rows_per_sec)
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    io::Read,
+};
+
+use crate::{
+    engine::{
+        transaction::{TransactionInfo, TxAction},
+        ClientId, TxId, Wallet,
+    },
+    record::csv_message,
+    EngineError, Record,
+};
+
+/// Problem with a row of the input, found by [`crate::validate_transactions`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+    /// line of the input, if known
+    pub line: Option<u64>,
+    pub message: String,
+}
+
+impl Display for ValidationIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {line}: {}", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Result of validation of the whole input
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    /// all rows read, including invalid ones
+    pub rows: usize,
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Deposit or withdrawal seen so far, which later rows can refer to
+struct SeenTx {
+    client_id: ClientId,
+    line: Option<u64>,
+    is_deposit: bool,
+}
+
+/// Read the entire input and collect every problem instead of stopping on the first one. Besides
+/// the checks done while processing, it looks for duplicate transaction ids, disputes, resolves
+/// and chargebacks of transactions which weren't deposited by the client before and deposits
+/// which would go over the largest amount a wallet can hold
+pub(crate) fn validate<R: Read>(io_reader: R) -> Result<ValidationReport, EngineError> {
+    let (mut rdr, lines) = Record::csv_reader(io_reader);
+    let headers = rdr.headers()?.clone();
+
+    let mut report = ValidationReport::default();
+    let header = headers.iter().collect::<Vec<_>>().join(",");
+    if !Record::is_csv_header(&header) {
+        report.issues.push(ValidationIssue {
+            line: Some(lines.input_line(1)),
+            message: format!("header should be \"type,client,tx,amount\", found \"{header}\""),
+        });
+    }
+
+    let mut seen = HashMap::<TxId, SeenTx>::new();
+    // rough balance of every client, disputes aside, to find deposits the wallet can't hold
+    let mut balances = HashMap::<ClientId, f64>::new();
+    for row in rdr.into_records() {
+        report.rows += 1;
+        let row = match row {
+            Ok(row) => row,
+            Err(e) if e.is_io_error() => return Err(e.into()),
+            Err(e) => {
+                let line = e.position().map(|pos| lines.input_line(pos.line()));
+                report.issues.push(ValidationIssue { line, message: csv_message(&e) });
+                continue;
+            },
+        };
+
+        let line = row.position().map(|pos| lines.input_line(pos.line()));
+        let record = match row.deserialize::<Record>(Some(&headers)) {
+            Ok(record) => Record { line, ..record },
+            Err(e) => {
+                report.issues.push(ValidationIssue { line, message: csv_message(&e) });
+                continue;
+            },
+        };
+
+        let (client_id, tx_id) = (record.client_id, record.tx_id);
+        let tx_info = match TransactionInfo::from_record(record) {
+            Ok(tx_info) => tx_info,
+            Err(e) => {
+                let message = match e {
+                    EngineError::RecordError(message) => message,
+                    e => e.to_string(),
+                };
+                report.issues.push(ValidationIssue { line, message });
+                continue;
+            },
+        };
+
+        let message = match tx_info.tx() {
+            TxAction::Deposit(_) | TxAction::Withdrawal(_) => match seen.get(&tx_id) {
+                Some(first) => Some(match first.line {
+                    Some(first_line) => {
+                        format!("duplicate transaction id {tx_id}, first used at line {first_line}")
+                    },
+                    None => format!("duplicate transaction id {tx_id}"),
+                }),
+                None => {
+                    let is_deposit = matches!(tx_info.tx(), TxAction::Deposit(_));
+                    seen.insert(tx_id, SeenTx { client_id, line, is_deposit });
+                    let balance = balances.entry(client_id).or_default();
+                    match *tx_info.tx() {
+                        TxAction::Deposit(amount)
+                            if *balance + f64::from(amount) > f64::from(Wallet::MAX_AMOUNT) =>
+                        {
+                            Some(format!(
+                                "deposit would bring the balance of client {client_id} over the \
+                                 largest supported amount"
+                            ))
+                        },
+                        TxAction::Deposit(amount) => {
+                            *balance += f64::from(amount);
+                            None
+                        },
+                        TxAction::Withdrawal(amount) => {
+                            if *balance >= f64::from(amount) {
+                                *balance -= f64::from(amount);
+                            }
+                            None
+                        },
+                        _ => None,
+                    }
+                },
+            },
+            action => {
+                let ty = action.ty().map(|ty| ty.to_string()).unwrap_or_default();
+                match seen.get(&tx_id) {
+                    None => Some(format!("{ty} of unknown transaction {tx_id}")),
+                    Some(deposit) if deposit.client_id != client_id => Some(format!(
+                        "{ty} of transaction {tx_id} which belongs to client {}",
+                        deposit.client_id
+                    )),
+                    Some(deposit) if !deposit.is_deposit => {
+                        Some(format!("{ty} of withdrawal {tx_id}, only deposits can be disputed"))
+                    },
+                    Some(_) => None,
+                }
+            },
+        };
+        if let Some(message) = message {
+            report.issues.push(ValidationIssue { line, message });
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,205 @@
+//! Differential testing: random transaction streams are processed by the actor based engine and by
+//! a plain single threaded model of the same rules, and both must agree on the outcome of every
+//! transaction and on the final balances. Amounts are multiples of 0.25, which f32 represents
+//! exactly, or of 0.1, which it does not, so balances are compared at the configured precision
+
+use std::collections::{BTreeMap, HashMap};
+
+use engine::{ClientId, Engine, EngineConfig, OutputOrder, Record, TxId, TxOutcome, TxRejection};
+use rust_decimal::Decimal;
+
+const STREAMS: u64 = 200;
+const RECORDS_PER_STREAM: usize = 300;
+const CLIENTS: u64 = 5;
+/// largest amount in quarters, so 25.0
+const MAX_QUARTERS: u64 = 100;
+/// largest amount in tenths, so 10.0
+const MAX_TENTHS: u64 = 100;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn engine_agrees_with_reference_model() {
+    for seed in 1..=STREAMS {
+        let records = generate(seed);
+
+        let mut model = Model { precision: EngineConfig::default().precision, ..Model::default() };
+        let expected = records.iter().map(|r| model.apply(r)).collect::<Vec<_>>();
+
+        let mut engine = Engine::new();
+        let mut outcomes = Vec::with_capacity(records.len());
+        for outcome in engine.submit_records(records.clone()).await.unwrap() {
+            outcomes.push(outcome.await.unwrap());
+        }
+        let balances = engine
+            .balances(OutputOrder::ClientId)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|b| (b.client, (b.available, b.held, b.total, b.locked)))
+            .collect::<BTreeMap<_, _>>();
+
+        if let Some(idx) = (0..records.len()).find(|idx| outcomes[*idx] != expected[*idx]) {
+            panic!(
+                "seed {seed}: line {} was {:?} in the engine, but {:?} in the model\n{}",
+                idx + 2,
+                outcomes[idx],
+                expected[idx],
+                to_csv(&records)
+            );
+        }
+        assert_eq!(
+            balances,
+            model.balances(),
+            "seed {seed}: balances differ\n{}",
+            to_csv(&records)
+        );
+    }
+}
+
+/// Rules of the engine with its default config, written as simply as possible
+#[derive(Default)]
+struct Model {
+    precision: u32,
+    accounts: BTreeMap<ClientId, Account>,
+}
+
+#[derive(Default)]
+struct Account {
+    available: Decimal,
+    held: Decimal,
+    locked: bool,
+    /// deposits which can be disputed, with whether they are disputed now
+    deposits: HashMap<TxId, (Decimal, bool)>,
+}
+
+impl Model {
+    fn apply(&mut self, record: &Record) -> TxOutcome {
+        let account = self.accounts.entry(record.client_id).or_default();
+        if account.locked {
+            return Err(TxRejection::AccountLocked);
+        }
+
+        let amount =
+            record.amount.map(|amount| Decimal::try_from(amount).unwrap().round_dp(self.precision));
+        match (record.ty.as_str(), amount) {
+            ("deposit", Some(amount)) => {
+                account.available += amount;
+                account.deposits.insert(record.tx_id, (amount, false));
+            },
+            ("withdrawal", Some(amount)) => {
+                if account.available < amount {
+                    return Err(TxRejection::InsufficientFunds);
+                }
+                account.available -= amount;
+            },
+            ("dispute", None) => {
+                let (amount, disputed) =
+                    account.deposits.get_mut(&record.tx_id).ok_or(TxRejection::UnknownTx)?;
+                if *disputed {
+                    return Err(TxRejection::AlreadyDisputed);
+                }
+                if account.available < *amount {
+                    return Err(TxRejection::InsufficientFunds);
+                }
+                account.available -= *amount;
+                account.held += *amount;
+                *disputed = true;
+            },
+            ("resolve", None) => {
+                let (amount, disputed) =
+                    account.deposits.get_mut(&record.tx_id).ok_or(TxRejection::UnknownTx)?;
+                if !*disputed {
+                    return Err(TxRejection::NotDisputed);
+                }
+                account.available += *amount;
+                account.held -= *amount;
+                *disputed = false;
+            },
+            ("chargeback", None) => {
+                let (amount, disputed) =
+                    *account.deposits.get(&record.tx_id).ok_or(TxRejection::UnknownTx)?;
+                if !disputed {
+                    return Err(TxRejection::NotDisputed);
+                }
+                account.held -= amount;
+                account.locked = true;
+                account.deposits.remove(&record.tx_id);
+            },
+            _ => unreachable!("generated records are always correct"),
+        }
+        Ok(())
+    }
+
+    fn balances(&self) -> BTreeMap<ClientId, (Decimal, Decimal, Decimal, bool)> {
+        self.accounts
+            .iter()
+            .map(|(client, account)| {
+                let total = account.available + account.held;
+                (*client, (account.available, account.held, total, account.locked))
+            })
+            .collect()
+    }
+}
+
+/// Random stream of correct records. Disputes, resolves and chargebacks mostly refer to earlier
+/// deposits, sometimes to deposits of other clients, withdrawals or unknown transactions
+fn generate(seed: u64) -> Vec<Record> {
+    let mut rng = XorShift(seed);
+    let mut records = Vec::with_capacity(RECORDS_PER_STREAM);
+    let mut next_tx: TxId = 1;
+    for _ in 0..RECORDS_PER_STREAM {
+        let client = (rng.below(CLIENTS) + 1) as ClientId;
+        let (ty, tx, amount) = match rng.below(10) {
+            0..=3 => ("deposit", None, Some(rng.amount())),
+            4..=5 => ("withdrawal", None, Some(rng.amount())),
+            kind => {
+                let ty = ["dispute", "resolve", "chargeback", "dispute"][kind as usize - 6];
+                // a few ids past the last one are never used
+                (ty, Some(rng.below(next_tx as u64 + 3) as TxId), None)
+            },
+        };
+        let tx_id = tx.unwrap_or_else(|| {
+            next_tx += 1;
+            next_tx - 1
+        });
+        records.push(Record {
+            ty: ty.to_string(),
+            client_id: client,
+            tx_id,
+            amount,
+            line: Some(records.len() as u64 + 2),
+        });
+    }
+    records
+}
+
+fn to_csv(records: &[Record]) -> String {
+    let rows = records.iter().map(|r| {
+        let amount = r.amount.map(|amount| amount.to_string()).unwrap_or_default();
+        format!("{},{},{},{amount}", r.ty, r.client_id, r.tx_id)
+    });
+    std::iter::once("type,client,tx,amount".to_string()).chain(rows).collect::<Vec<_>>().join("\n")
+}
+
+/// small deterministic generator, so a failing seed can be replayed
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    fn amount(&mut self) -> f32 {
+        if self.below(2) == 0 {
+            (self.below(MAX_QUARTERS) + 1) as f32 / 4.0
+        } else {
+            (self.below(MAX_TENTHS) + 1) as f32 / 10.0
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Golden scenarios: every `<name>.in.csv` in `tests/scenarios` is processed with balances sorted
+//! by client id and compared with `<name>.expected.csv`. New regression cases need only these two
+//! files. Run with `UPDATE_SCENARIOS=1` to write the current output as expected instead
+
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use engine::{OutputOrder, ProcessOptions};
+
+const SCENARIOS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/scenarios");
+const INPUT_SUFFIX: &str = ".in.csv";
+const EXPECTED_SUFFIX: &str = ".expected.csv";
+
+#[test]
+fn golden_scenarios() {
+    let update = std::env::var_os("UPDATE_SCENARIOS").is_some();
+    let scenarios = scenarios(Path::new(SCENARIOS_DIR));
+    assert!(!scenarios.is_empty(), "no scenarios found in {SCENARIOS_DIR}");
+
+    let mut failures = Vec::new();
+    for (name, input) in scenarios.iter() {
+        let expected_path = input.with_file_name(format!("{name}{EXPECTED_SUFFIX}"));
+        let actual = match run(input) {
+            Ok(actual) => actual,
+            Err(e) => {
+                failures.push(format!("{name}: processing failed: {e}"));
+                continue;
+            },
+        };
+
+        if update {
+            std::fs::write(&expected_path, &actual).unwrap();
+            continue;
+        }
+        match std::fs::read_to_string(&expected_path) {
+            Ok(expected) if normalize(&expected) == normalize(&actual) => {},
+            Ok(expected) => failures.push(format!(
+                "{name}: output differs (- expected, + actual)\n{}",
+                line_diff(&normalize(&expected), &normalize(&actual))
+            )),
+            Err(e) => failures.push(format!("{name}: can't read {}: {e}", expected_path.display())),
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} scenarios failed\n\n{}",
+        failures.len(),
+        scenarios.len(),
+        failures.join("\n")
+    );
+}
+
+/// names and inputs of all scenarios, sorted by name
+fn scenarios(dir: &Path) -> Vec<(String, PathBuf)> {
+    let mut scenarios = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter_map(|path| {
+            let name = path.file_name()?.to_str()?.strip_suffix(INPUT_SUFFIX)?.to_string();
+            Some((name, path))
+        })
+        .collect::<Vec<_>>();
+    scenarios.sort();
+    scenarios
+}
+
+fn run(input: &Path) -> Result<String, engine::EngineError> {
+    let options = ProcessOptions { order: OutputOrder::ClientId, ..Default::default() };
+    let mut output = Vec::new();
+    engine::process_transactions_with_options(std::fs::File::open(input)?, &mut output, &options)?;
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+/// lines without trailing whitespace, so expected files may be edited on any platform
+fn normalize(output: &str) -> Vec<&str> {
+    output.lines().map(str::trim_end).filter(|line| !line.is_empty()).collect()
+}
+
+/// lines of both outputs prefixed with ` `, `-` (only expected) or `+` (only actual), based on
+/// their longest common subsequence
+fn line_diff(expected: &[&str], actual: &[&str]) -> String {
+    let (n, m) = (expected.len(), actual.len());
+    let mut common = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            common[i][j] = if expected[i] == actual[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && expected[i] == actual[j] {
+            let _ = writeln!(diff, "  {}", expected[i]);
+            (i, j) = (i + 1, j + 1);
+        } else if j == m || (i < n && common[i + 1][j] >= common[i][j + 1]) {
+            let _ = writeln!(diff, "- {}", expected[i]);
+            i += 1;
+        } else {
+            let _ = writeln!(diff, "+ {}", actual[j]);
+            j += 1;
+        }
+    }
+    diff
+}
+
+#[test]
+fn diff_shows_changed_lines() {
+    let diff = line_diff(&["client", "1,1", "2,2"], &["client", "1,5", "2,2", "3,3"]);
+    assert_eq!(diff, "  client\n- 1,1\n+ 1,5\n  2,2\n+ 3,3\n");
+}